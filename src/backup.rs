@@ -0,0 +1,141 @@
+//! backup  
+//! 安装前备份将被覆盖的文件，用于一键回滚
+use crate::installer::ComponentKind;
+use crate::utils::*;
+use crate::version_toml::*;
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const BACKUP_DIR: &str = "backup";
+/// 每个组件保留的备份数量
+const KEEP_SNAPSHOTS: usize = 3;
+/// 备份目录里记录旧版本信息的文件
+const SNAPSHOT_INFO: &str = "version.toml";
+
+/// 备份local对应的已安装文件，返回备份目录  
+/// 没有本地记录或本地没有任何文件时不备份
+pub fn snapshot(key: &str, local: &VersionInfo) -> Result<Option<String>> {
+    let files: Vec<_> = local
+        .filelist
+        .iter()
+        .filter_map(|f| local.local_file(f).ok().map(|path| (f, path)))
+        .filter(|(_, path)| Path::new(path).exists())
+        .collect();
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let label: String = local
+        .version()
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    let dir = format!(
+        "{BACKUP_DIR}/{key}/{}_{label}",
+        Local::now().format("%Y%m%d%H%M%S")
+    );
+    for (filename, path) in files {
+        let target = confine_path(&dir, filename)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        info!("Backup {path} -> {target:?}");
+        fs::copy(&path, &target)?;
+    }
+    fs::write(
+        PathBuf::from(&dir).join(SNAPSHOT_INFO),
+        toml::to_string_pretty(local)?,
+    )?;
+    prune(key);
+    Ok(Some(dir))
+}
+
+/// 只保留最近的几个备份
+fn prune(key: &str) {
+    let Ok(entries) = fs::read_dir(format!("{BACKUP_DIR}/{key}")) else {
+        return;
+    };
+    let mut dirs: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    // 目录名以时间开头，按名字排序即按时间排序
+    dirs.sort();
+    let remove = dirs.len().saturating_sub(KEEP_SNAPSHOTS);
+    for dir in &dirs[..remove] {
+        info!("删除旧备份 {dir:?}");
+        if let Err(e) = fs::remove_dir_all(dir) {
+            warn!("删除旧备份{dir:?}失败: {e}");
+        }
+    }
+}
+
+/// 备份里记录的旧版本信息
+pub fn load_snapshot(key: &str, dir: &str) -> Result<VersionInfo> {
+    let path = PathBuf::from(dir).join(SNAPSHOT_INFO);
+    let content = fs::read_to_string(&path).map_err(|_| anyhow!("备份 {dir} 不存在或已损坏"))?;
+    Ok(VersionInfo { key: key.to_string(), ..toml::from_str(&content)? })
+}
+
+/// 把备份的文件恢复到安装目录，返回旧版本信息  
+/// 更新工具自身的exe恢复为临时文件，由调用方替换
+pub fn restore(key: &str, dir: &str) -> Result<VersionInfo> {
+    let old = load_snapshot(key, dir)?;
+    let is_self = old.kind() == ComponentKind::SelfUpdate;
+    for (i, filename) in old.filelist.iter().enumerate() {
+        let backup = confine_path(dir, filename)?;
+        if !backup.exists() {
+            continue;
+        }
+        let target = if is_self && i == 0 {
+            tempfile_path(filename)?
+        } else {
+            old.local_file(filename)?
+        };
+        if let Some(parent) = Path::new(&target).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        info!("Restore {backup:?} -> {target}");
+        fs::copy(&backup, &target)?;
+    }
+    Ok(old)
+}
+
+#[cfg(test)]
+#[test]
+fn test_snapshot_restore() -> Result<()> {
+    let info = |date: &str| VersionInfo {
+        name: "测试".to_string(),
+        date: date.to_string(),
+        filelist: vec!["a.bin".to_string(), "sub/b.bin".to_string()],
+        install_path: Some("test_backup_dir".to_string()),
+        ..Default::default()
+    };
+    let old = info("2025-02-01 13:54:57");
+    fs::create_dir_all("test_backup_dir/sub")?;
+    fs::write("test_backup_dir/a.bin", b"old a")?;
+    fs::write("test_backup_dir/sub/b.bin", b"old b")?;
+
+    let dir = snapshot("test_backup", &old)?.expect("no snapshot");
+    assert!(dir.ends_with("2025-02-01"));
+    fs::write("test_backup_dir/a.bin", b"new a")?;
+    fs::remove_file("test_backup_dir/sub/b.bin")?;
+
+    let restored = restore("test_backup", &dir)?;
+    assert_eq!(restored.date, old.date);
+    assert_eq!(fs::read("test_backup_dir/a.bin")?, b"old a");
+    assert_eq!(fs::read("test_backup_dir/sub/b.bin")?, b"old b");
+
+    // 没有文件时不备份
+    let mut missing = info("2025-02-11 13:54:57");
+    missing.install_path = Some("test_backup_missing".to_string());
+    assert!(snapshot("test_backup", &missing)?.is_none());
+    fs::remove_dir_all("test_backup_dir")?;
+    fs::remove_dir_all(format!("{BACKUP_DIR}/test_backup"))?;
+    let _ = fs::remove_dir(BACKUP_DIR);
+    Ok(())
+}
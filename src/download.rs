@@ -1,12 +1,22 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use futures_core::stream::Stream;
-use reqwest::Client;
-use reqwest::header::REFERER;
+use log::{info, warn};
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use futures_channel::mpsc::{Sender, Receiver};
 use futures_util::{SinkExt, StreamExt};
-use std::fs::File;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::fs::{self, File};
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::{Id, JoinError, JoinSet};
+use crate::settings::{Channel, DownloadLimits, Mirror};
+use crate::utils::{apply_patch, tempfile_path, FileHash};
 use crate::Message;
 
 /// 下载线程的消息监听线程  
@@ -26,70 +36,290 @@ pub fn listener() -> impl Stream<Item = Message> {
     })
 }
 
+/// 进度消息的最小间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 文件的下载进度，也用于合并同一组件所有文件的进度
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DownloadProgress {
+    pub key: String,
+    pub filename: String,
+    /// 已下载的字节数，包括续传前已有的部分
+    pub downloaded: u64,
+    /// 总大小，服务器没有返回时为None
+    pub total: Option<u64>,
+    /// 本次连接的平均速度，字节/秒
+    pub rate: f64,
+    /// 预计剩余时间
+    pub eta: Option<Duration>,
+}
+
+impl DownloadProgress {
+    /// received为本次连接收到的字节数，用于计算速度
+    fn new(file: &DownloadFile, downloaded: u64, total: Option<u64>, received: u64, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64();
+        let rate = if secs > 0.0 { received as f64 / secs } else { 0.0 };
+        DownloadProgress {
+            key: file.key.clone(),
+            filename: file.filename.clone(),
+            downloaded,
+            total,
+            rate,
+            eta: Self::eta(downloaded, total, rate),
+        }
+    }
+
+    /// 刚放进队列还没开始下载，总大小取清单记录
+    pub fn queued(file: &DownloadFile) -> Self {
+        Self::new(file, 0, file.size, 0, Duration::ZERO)
+    }
+
+    fn eta(downloaded: u64, total: Option<u64>, rate: f64) -> Option<Duration> {
+        let remaining = total?.saturating_sub(downloaded);
+        (rate > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    /// 合并多个文件的进度，有任何文件不知道总大小时总大小未知
+    pub fn sum<'a>(items: impl IntoIterator<Item = &'a DownloadProgress>) -> Option<Self> {
+        let mut items = items.into_iter();
+        let first = items.next()?.clone();
+        let mut ret = items.fold(first, |mut acc, p| {
+            acc.downloaded += p.downloaded;
+            acc.total = acc.total.zip(p.total).map(|(a, b)| a + b);
+            acc.rate += p.rate;
+            acc
+        });
+        ret.eta = Self::eta(ret.downloaded, ret.total, ret.rate);
+        Some(ret)
+    }
+
+    /// 完成比例，0到1
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            Some(total) if total > 0 => (self.downloaded as f32 / total as f32).min(1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+/// 可用的增量补丁
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct DownloadPatch {
+    /// 补丁文件名
+    pub patch: String,
+    pub format: String,
+    /// 本地旧文件路径
+    pub base: String,
+    /// 新文件的Hash，用于校验补丁结果
+    pub expected: String
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct DownloadFile {
     pub key: String,
-    pub filename: String
+    pub filename: String,
+    /// 是否为完整压缩包
+    pub package: bool,
+    /// 增量补丁，失败时下载完整文件
+    pub patch: Option<DownloadPatch>,
+    /// 清单记录的文件大小，下载结果不符时视为失败
+    pub size: Option<u64>,
+    /// 文件所在的频道
+    pub channel: Channel
 }
 
 impl DownloadFile {
-    pub fn tempfile(&self) -> String {
-        format!("{}.autoupdate", self.filename)
+    pub fn new(key: &str, filename: &str) -> Self {
+        DownloadFile { key: key.to_string(), filename: filename.to_string(), ..Default::default() }
     }
 
-    pub fn url(&self) -> String {
-        format!("https://cdn2.viktorlab.cn/uma/{}/{}", self.key, self.filename)
+    pub fn package(key: &str, package: &str) -> Self {
+        DownloadFile { package: true, ..Self::new(key, package) }
+    }
+
+    /// 下载的临时文件，不能离开工作目录
+    pub fn tempfile(&self) -> Result<String> {
+        tempfile_path(&self.filename)
+    }
+
+    pub fn url(&self, mirror: &Mirror) -> String {
+        format!("{}/{}/{}", self.channel.dir(mirror), self.key, self.filename)
     }
 }
 
-pub struct DownloadWorker {
+/// 断点续传记录，放在临时文件旁边  
+/// 下载完成后删除，临时文件存在但没有记录时无法确认内容，重新下载完整文件
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+struct ResumeInfo {
+    /// 完整文件的大小
+    size: Option<u64>,
+    /// ETag或Last-Modified，续传时作为If-Range
+    validator: String,
+}
+
+impl ResumeInfo {
+    fn path(tempfile: &str) -> String {
+        format!("{tempfile}.resume")
+    }
+
+    fn load(tempfile: &str) -> Option<Self> {
+        toml::from_str(&fs::read_to_string(Self::path(tempfile)).ok()?).ok()
+    }
+
+    fn save(&self, tempfile: &str) -> Result<()> {
+        fs::write(Self::path(tempfile), toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn remove(tempfile: &str) {
+        let _ = fs::remove_file(Self::path(tempfile));
+    }
+
+    /// 已下载的部分不能再用，删除临时文件和记录
+    fn discard(tempfile: &str) {
+        let _ = fs::remove_file(tempfile);
+        Self::remove(tempfile);
+    }
+}
+
+/// 删除下载了一部分的临时文件和续传记录，不再续传时调用
+pub fn discard_partial(tempfile: &str) {
+    ResumeInfo::discard(tempfile);
+}
+
+/// Content-Range `bytes 100-199/200`的起始位置
+fn content_range_start(resp: &Response) -> Option<u64> {
+    resp.headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// 单个文件的重试策略，等待时间按指数增长并加上随机抖动
+#[derive(Clone, Copy, Debug)]
+struct RetryPolicy {
+    /// 最多尝试的次数，包括第一次
+    attempts: u32,
+    /// 第一次重试前的等待时间
+    base: Duration,
+    /// 等待时间上限
+    max: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 4,
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第n次重试前的等待时间，在[d/2, d)之间随机，避免多个文件同时重试
+    fn delay(&self, retry: u32) -> Duration {
+        let full = self.base.saturating_mul(1 << retry.min(16)).min(self.max);
+        let jitter = RandomState::new().build_hasher().finish() % 1000;
+        full / 2 + full / 2 * jitter as u32 / 1000
+    }
+}
+
+/// 下载任务共享的状态，每个任务持有一份clone
+#[derive(Clone)]
+struct Downloader {
     cli: Client,
     /// 用于向主线程发送消息
     channel: Sender<Message>,
+    /// 更新服务器列表
+    mirrors: Arc<Vec<Mirror>>,
+    /// 上一次成功下载的服务器，下次优先使用
+    active: Arc<Mutex<Option<usize>>>,
+    /// 每台服务器的并发限制，同一host的服务器共用
+    hosts: Arc<HashMap<String, Arc<Semaphore>>>,
+    retry: RetryPolicy,
+}
+
+/// 下载线程，从队列取出文件后交给任务池并发下载
+pub struct DownloadWorker {
+    downloader: Downloader,
     /// 用于接收要下载的文件信息
-    control: Receiver<DownloadFile>
+    control: Receiver<DownloadFile>,
+    /// 总并发限制
+    slots: Arc<Semaphore>,
 }
 
 impl DownloadWorker {
-    pub fn new(channel: Sender<Message>, control: Receiver<DownloadFile>) -> Self {
+    pub fn new(channel: Sender<Message>, control: Receiver<DownloadFile>, mirrors: Vec<Mirror>, limits: DownloadLimits) -> Self {
+        let hosts = mirrors
+            .iter()
+            .map(|m| (m.host(), Arc::new(Semaphore::new(limits.per_host.max(1)))))
+            .collect();
         DownloadWorker {
-            cli: Client::new(),
-            channel,
-            control
+            downloader: Downloader {
+                cli: Client::new(),
+                channel,
+                mirrors: Arc::new(mirrors),
+                active: Arc::new(Mutex::new(None)),
+                hosts: Arc::new(hosts),
+                retry: RetryPolicy::default(),
+            },
+            control,
+            slots: Arc::new(Semaphore::new(limits.concurrency.max(1))),
         }
     }
+
+    /// 每个文件无论成功失败都会发出一条完成或失败消息
     pub async fn run_guarded(&mut self) -> Result<()> {
-        while let Some(file) = self.control.next().await {
-            let resp = self.cli.get(file.url())
-                .header(REFERER, "https://viktorlab.cn")
-                .send().await?;
-            let total_size = resp.content_length().unwrap_or(1) as usize;
-            let mut stream = resp.bytes_stream();
-
-            let mut temp_file = File::create(file.tempfile())?;
-            let mut downloaded_size = 0;
-            let mut last_progress = 0;
-
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk?;
-                temp_file.write_all(&chunk)?;
-                downloaded_size += chunk.len();
-                // 减少消息数量
-                if downloaded_size - last_progress > 128000 {
-                    self.channel.start_send(
-                        Message::OnSetInfo(format!(
-                            "下载 [{}]{} ({} / {})",
-                            file.key,
-                            file.filename,
-                            downloaded_size,
-                            total_size
-                        ))
-                    )?;
-                    last_progress = downloaded_size;
+        let mut tasks = JoinSet::new();
+        // 正在下载的文件，任务异常退出时用于报告失败
+        let mut running = HashMap::new();
+        loop {
+            tokio::select! {
+                file = self.control.next() => {
+                    let Some(file) = file else { break };
+                    // 等到有空位再取下一个文件
+                    let slot = self.slots.clone().acquire_owned().await?;
+                    let mut downloader = self.downloader.clone();
+                    let task = file.clone();
+                    let handle = tasks.spawn(async move {
+                        // 单个文件失败不影响队列里的其他文件
+                        let msg = match downloader.download_with_retry(&task).await {
+                            Ok(_) => Message::OnDownloadCompleted(task),
+                            Err(e) => Message::OnDownloadFailed(task, e.to_string()),
+                        };
+                        drop(slot);
+                        msg
+                    });
+                    running.insert(handle.id(), file);
                 }
+                Some(ret) = tasks.join_next_with_id() => self.finish(ret, &mut running).await?,
+            }
+        }
+        while let Some(ret) = tasks.join_next_with_id().await {
+            self.finish(ret, &mut running).await?;
+        }
+        Ok(())
+    }
+
+    /// 把任务结果发给主线程
+    async fn finish(&mut self, ret: Result<(Id, Message), JoinError>, running: &mut HashMap<Id, DownloadFile>) -> Result<()> {
+        let msg = match ret {
+            Ok((id, msg)) => {
+                running.remove(&id);
+                msg
+            }
+            Err(e) => {
+                let file = running.remove(&e.id()).ok_or(anyhow!("未知的下载任务: {e}"))?;
+                Message::OnDownloadFailed(file, format!("下载任务异常退出: {e}"))
             }
-            self.channel.start_send(Message::OnDownloadCompleted(file))?;
-        }        
+        };
+        self.downloader.channel.send(msg).await?;
         Ok(())
     }
 
@@ -100,7 +330,7 @@ impl DownloadWorker {
             match self.run_guarded().await {
                 Ok(_) => break,
                 Err(e) => {
-                    self.channel
+                    self.downloader.channel
                         .send(Message::OnSetInfo(format!("下载失败: {e}")))
                         .await.unwrap();
                 }
@@ -108,3 +338,508 @@ impl DownloadWorker {
         }
     }
 }
+
+impl Downloader {
+    /// 从上次成功的服务器开始依次尝试，连接失败或返回4xx/5xx时换下一个  
+    /// resume为(已下载字节数, If-Range)，服务器可能忽略而返回完整文件，
+    /// 也可能返回416表示已下载的部分超出了文件大小  
+    /// 返回的许可要保持到响应读完，用于限制同一服务器的并发数
+    async fn request(&mut self, file: &DownloadFile, resume: Option<(u64, &str)>) -> Result<(Response, OwnedSemaphorePermit)> {
+        let start = self.active.lock().unwrap().unwrap_or(0);
+        let mut errors = vec![];
+        for i in 0..self.mirrors.len() {
+            let index = (start + i) % self.mirrors.len();
+            let mirror = &self.mirrors[index];
+            let url = file.url(mirror);
+            let permit = self.hosts[&mirror.host()].clone().acquire_owned().await?;
+            let mut req = mirror.get(&self.cli, &url);
+            if let Some((offset, validator)) = resume {
+                req = req
+                    .header(RANGE, format!("bytes={offset}-"))
+                    .header(IF_RANGE, validator);
+            }
+            match req.send().await {
+                Ok(resp) if resp.status().is_success()
+                    || (resume.is_some() && resp.status() == StatusCode::RANGE_NOT_SATISFIABLE) => {
+                    let changed = self.active.lock().unwrap().replace(index) != Some(index);
+                    if changed {
+                        self.channel.send(Message::OnSetMirror(mirror.url.clone())).await?;
+                    }
+                    return Ok((resp, permit));
+                }
+                Ok(resp) => {
+                    warn!("{url}: {}", resp.status());
+                    errors.push(format!("{url}: {}", resp.status()));
+                }
+                Err(e) => {
+                    warn!("{url}: {e}");
+                    errors.push(format!("{url}: {e}"));
+                }
+            }
+        }
+        Err(anyhow!("{} 下载失败: {}", file.filename, errors.join("; ")))
+    }
+
+    /// 下载失败时等待一段时间后重试，次数用完后返回最后一次的错误
+    async fn download_with_retry(&mut self, file: &DownloadFile) -> Result<()> {
+        let mut retry = 0;
+        loop {
+            let err = match self.download(file).await {
+                Ok(_) => return Ok(()),
+                Err(e) => e,
+            };
+            retry += 1;
+            if retry >= self.retry.attempts {
+                return Err(anyhow!("{err}（已重试{}次）", retry - 1));
+            }
+            let delay = self.retry.delay(retry - 1);
+            warn!("{} 下载失败: {err}，{}ms后第{retry}次重试", file.filename, delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn download(&mut self, file: &DownloadFile) -> Result<()> {
+        if let Some(patch) = &file.patch {
+            match self.download_patch(file, patch).await {
+                Ok(_) => return Ok(()),
+                Err(e) => warn!("{} 增量更新失败，改为下载完整文件: {e}", file.filename),
+            }
+        }
+        self.download_full(file).await
+    }
+
+    /// 下载补丁并应用到本地旧文件，结果校验通过后写入临时文件
+    async fn download_patch(&mut self, file: &DownloadFile, patch: &DownloadPatch) -> Result<()> {
+        let patch_file = DownloadFile { channel: file.channel, ..DownloadFile::new(&file.key, &patch.patch) };
+        let (resp, _permit) = self.request(&patch_file, None).await?;
+        let patch_data = resp.bytes().await?;
+        let base = std::fs::read(&patch.base)?;
+        let contents = apply_patch(&patch.format, &base, &patch_data)?;
+        let expected: FileHash = patch.expected.parse()?;
+        if expected.algo.digest(&contents) != expected.hex {
+            return Err(anyhow!("补丁结果{}错误", expected.algo.name()));
+        }
+        info!("{} 增量更新 {} -> {} 字节", file.filename, patch_data.len(), contents.len());
+        let tempfile = file.tempfile()?;
+        std::fs::write(&tempfile, &contents)?;
+        ResumeInfo::remove(&tempfile);
+        Ok(())
+    }
+
+    /// 下载完整文件，有续传记录时从已下载的位置继续
+    async fn download_full(&mut self, file: &DownloadFile) -> Result<()> {
+        let tempfile = file.tempfile()?;
+        let mut resume = ResumeInfo::load(&tempfile)
+            .map(|info| (fs::metadata(&tempfile).map(|m| m.len()).unwrap_or(0), info))
+            .filter(|(offset, _)| *offset > 0);
+        match resume.as_ref().map(|(offset, info)| (*offset, info.size)) {
+            // 上次下载完但没来得及删除记录
+            Some((offset, Some(size))) if offset == size => {
+                info!("{} 已下载完整，不再请求", file.filename);
+                ResumeInfo::remove(&tempfile);
+                let progress = DownloadProgress::new(file, size, Some(size), 0, Duration::ZERO);
+                self.channel.send(Message::OnDownloadProgress(progress)).await?;
+                return check_size(file, &tempfile, size);
+            }
+            Some((offset, Some(size))) if offset > size => {
+                warn!("{} 已下载{offset}字节，超出文件大小{size}字节，重新下载", file.filename);
+                ResumeInfo::discard(&tempfile);
+                resume = None;
+            }
+            _ => {}
+        }
+        let mut sent = self
+            .request(file, resume.as_ref().map(|(offset, info)| (*offset, info.validator.as_str())))
+            .await?;
+        if sent.0.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            warn!("{} 续传位置超出文件大小，重新下载", file.filename);
+            // 先释放许可，同一服务器只允许一个连接时也能再次请求
+            drop(sent);
+            ResumeInfo::discard(&tempfile);
+            resume = None;
+            sent = self.request(file, None).await?;
+        }
+        let (resp, _permit) = sent;
+        let status = resp.status();
+        // 只接受完整文件或请求过的续传
+        if status != StatusCode::OK && !(resume.is_some() && status == StatusCode::PARTIAL_CONTENT) {
+            return Err(anyhow!("{} 服务器返回 {status}", file.filename));
+        }
+        let offset = match &resume {
+            Some((offset, _)) if status == StatusCode::PARTIAL_CONTENT => {
+                if content_range_start(&resp) != Some(*offset) {
+                    ResumeInfo::remove(&tempfile);
+                    return Err(anyhow!("{} 续传位置不符", file.filename));
+                }
+                info!("{} 从{offset}字节处继续下载", file.filename);
+                *offset
+            }
+            Some(_) => {
+                info!("{} 服务器不支持续传或文件已改变，重新下载", file.filename);
+                0
+            }
+            None => 0,
+        };
+        let expected = resp.content_length().map(|len| len + offset);
+        if let (Some(size), Some(expected)) = (file.size, expected) {
+            // 多半是错误页面，不写入临时文件
+            if size != expected {
+                ResumeInfo::remove(&tempfile);
+                return Err(anyhow!("{} 大小错误: 服务器返回{expected}字节，清单记录{size}字节", file.filename));
+            }
+        }
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        // 没有ETag和Last-Modified时无法安全续传
+        match header(ETAG).or(header(LAST_MODIFIED)) {
+            Some(validator) => ResumeInfo { size: expected, validator }.save(&tempfile)?,
+            None => ResumeInfo::remove(&tempfile),
+        }
+        let mut stream = resp.bytes_stream();
+
+        let mut temp_file = if offset > 0 {
+            fs::OpenOptions::new().append(true).open(&tempfile)?
+        } else {
+            File::create(&tempfile)?
+        };
+        let start = Instant::now();
+        let mut last_progress = start;
+        let mut received = offset;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            temp_file.write_all(&chunk)?;
+            received += chunk.len() as u64;
+            // 按时间限制消息数量
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                let progress = DownloadProgress::new(file, received, expected, received - offset, start.elapsed());
+                self.channel.send(Message::OnDownloadProgress(progress)).await?;
+                last_progress = Instant::now();
+            }
+        }
+        temp_file.flush()?;
+        let progress = DownloadProgress::new(file, received, expected, received - offset, start.elapsed());
+        self.channel.send(Message::OnDownloadProgress(progress)).await?;
+        // 连接提前关闭时保留已下载的部分，下次续传
+        if let Some(expected) = expected.filter(|len| *len != received) {
+            return Err(anyhow!("{} 下载不完整: {received} / {expected}字节", file.filename));
+        }
+        ResumeInfo::remove(&tempfile);
+        check_size(file, &tempfile, received)
+    }
+}
+
+/// 下载完的文件和清单记录的大小不符时删除临时文件
+fn check_size(file: &DownloadFile, tempfile: &str, received: u64) -> Result<()> {
+    if let Some(size) = file.size.filter(|size| *size != received) {
+        let _ = fs::remove_file(tempfile);
+        return Err(anyhow!("{} 大小错误: 收到{received}字节，清单记录{size}字节", file.filename));
+    }
+    Ok(())
+}
+
+/// 用本地CDN跑一遍下载线程，返回发给主线程的所有消息
+#[cfg(test)]
+async fn run_test_worker(mirrors: Vec<Mirror>, files: Vec<DownloadFile>) -> Vec<Message> {
+    let no_retry = RetryPolicy { attempts: 1, ..Default::default() };
+    run_test_pool(mirrors, files, DownloadLimits::default(), no_retry).await
+}
+
+#[cfg(test)]
+async fn run_test_pool(mirrors: Vec<Mirror>, files: Vec<DownloadFile>, limits: DownloadLimits, retry: RetryPolicy) -> Vec<Message> {
+    let (tx, rx) = futures_channel::mpsc::channel(256);
+    let (mut tx_file, rx_file) = futures_channel::mpsc::channel(16);
+    for file in files {
+        tx_file.start_send(file).unwrap();
+    }
+    drop(tx_file);
+    let mut worker = DownloadWorker::new(tx, rx_file, mirrors, limits);
+    worker.downloader.retry = retry;
+    worker.run_guarded().await.unwrap();
+    drop(worker);
+    rx.collect().await
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_download_patch() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+    use crate::utils::HashAlgo;
+
+    let base = b"old file contents ".repeat(64);
+    let new = [base.as_slice(), b"and a little more"].concat();
+    let mut encoder = zstd::stream::write::Encoder::with_dictionary(vec![], 19, &base)?;
+    encoder.write_all(&new)?;
+    let patch_data = encoder.finish()?;
+
+    let cdn = TestCdn::start().await;
+    cdn.put("/ai_data/test_patch.bin", new.clone());
+    cdn.put("/ai_data/test_patch.bin.zst", patch_data);
+    std::fs::write("test_patch.bin", &base)?;
+
+    let file = DownloadFile {
+        patch: Some(DownloadPatch {
+            patch: "test_patch.bin.zst".to_string(),
+            format: "zstd".to_string(),
+            base: "test_patch.bin".to_string(),
+            expected: format!("sha1:{}", HashAlgo::Sha1.digest(&new)),
+        }),
+        ..DownloadFile::new("ai_data", "test_patch.bin")
+    };
+    // 补丁正常
+    let msgs = run_test_worker(vec![Mirror::new(&cdn.base_url)], vec![file.clone()]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(std::fs::read("test_patch.bin.autoupdate")?, new);
+    // 补丁损坏时下载完整文件
+    cdn.put("/ai_data/test_patch.bin.zst", b"broken".to_vec());
+    std::fs::remove_file("test_patch.bin.autoupdate")?;
+    let msgs = run_test_worker(vec![Mirror::new(&cdn.base_url)], vec![file]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(std::fs::read("test_patch.bin.autoupdate")?, new);
+
+    std::fs::remove_file("test_patch.bin")?;
+    std::fs::remove_file("test_patch.bin.autoupdate")?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_resume_download() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let cdn = TestCdn::start().await;
+    cdn.put("/ai_data/test_resume.bin", data.clone());
+    let mirrors = vec![Mirror::new(&cdn.base_url)];
+    let file = DownloadFile::new("ai_data", "test_resume.bin");
+
+    // 连接中途断开，保留已下载的部分
+    cdn.cut("/ai_data/test_resume.bin", 50_000);
+    let msgs = run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadFailed(..))));
+    assert_eq!(fs::metadata("test_resume.bin.autoupdate")?.len(), 50_000);
+    let info = ResumeInfo::load("test_resume.bin.autoupdate").expect("no resume info");
+    assert_eq!(info.size, Some(200_000));
+
+    // 重试时用Range继续
+    let msgs = run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(fs::read("test_resume.bin.autoupdate")?, data);
+    assert!(ResumeInfo::load("test_resume.bin.autoupdate").is_none());
+    assert_eq!(cdn.log()[1], "206 /ai_data/test_resume.bin");
+
+    // 服务器忽略Range时重新下载完整文件
+    fs::remove_file("test_resume.bin.autoupdate")?;
+    cdn.cut("/ai_data/test_resume.bin", 50_000);
+    run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    cdn.ignore_ranges(true);
+    let msgs = run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(fs::read("test_resume.bin.autoupdate")?, data);
+
+    // 文件在服务器上改变时If-Range不符，也重新下载
+    cdn.ignore_ranges(false);
+    fs::remove_file("test_resume.bin.autoupdate")?;
+    cdn.cut("/ai_data/test_resume.bin", 50_000);
+    run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    let changed: Vec<u8> = data.iter().rev().cloned().collect();
+    cdn.put("/ai_data/test_resume.bin", changed.clone());
+    run_test_worker(mirrors, vec![file]).await;
+    assert_eq!(fs::read("test_resume.bin.autoupdate")?, changed);
+    assert_eq!(cdn.log().last().map(|s| s.as_str()), Some("200 /ai_data/test_resume.bin"));
+    fs::remove_file("test_resume.bin.autoupdate")?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_resume_offset() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let etag = format!("\"{}\"", blake3::hash(&data).to_hex());
+    let cdn = TestCdn::start().await;
+    cdn.put("/ai_data/test_offset.bin", data.clone());
+    let mirrors = vec![Mirror::new(&cdn.base_url)];
+    let file = DownloadFile::new("ai_data", "test_offset.bin");
+    let tempfile = "test_offset.bin.autoupdate";
+
+    // 已经下载完整时不再请求
+    fs::write(tempfile, &data)?;
+    ResumeInfo { size: Some(10_000), validator: etag.clone() }.save(tempfile)?;
+    let msgs = run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert!(cdn.log().is_empty());
+    assert!(ResumeInfo::load(tempfile).is_none());
+
+    // 超出记录的大小时丢弃后重新下载
+    fs::write(tempfile, [data.as_slice(), b"garbage"].concat())?;
+    ResumeInfo { size: Some(10_000), validator: etag.clone() }.save(tempfile)?;
+    let msgs = run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(fs::read(tempfile)?, data);
+    assert_eq!(cdn.log().last().map(|s| s.as_str()), Some("200 /ai_data/test_offset.bin"));
+
+    // 没有记录大小时由服务器返回416
+    fs::write(tempfile, [data.as_slice(), b"garbage"].concat())?;
+    ResumeInfo { size: None, validator: etag }.save(tempfile)?;
+    let msgs = run_test_worker(mirrors, vec![file]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(fs::read(tempfile)?, data);
+    assert_eq!(cdn.log()[1..], ["416 /ai_data/test_offset.bin", "200 /ai_data/test_offset.bin"]);
+    assert!(ResumeInfo::load(tempfile).is_none());
+    fs::remove_file(tempfile)?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_download_pool() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+    use std::time::Duration;
+
+    let cdn = TestCdn::start().await;
+    cdn.delay(Duration::from_millis(100));
+    let files: Vec<_> = (0..6)
+        .map(|i| {
+            let name = format!("test_pool_{i}.bin");
+            cdn.put(&format!("/ura_data/{name}"), name.repeat(100));
+            DownloadFile::new("ura_data", &name)
+        })
+        .collect();
+    let mirrors = vec![Mirror::new(&cdn.base_url)];
+
+    // 受同一服务器的限制
+    let limits = DownloadLimits { concurrency: 4, per_host: 2 };
+    let msgs = run_test_pool(mirrors.clone(), files.clone(), limits, RetryPolicy::default()).await;
+    let completed = msgs.iter().filter(|m| matches!(m, Message::OnDownloadCompleted(_))).count();
+    assert_eq!(completed, 6);
+    assert_eq!(cdn.max_active(), 2);
+
+    // 受总数的限制
+    let cdn2 = TestCdn::start().await;
+    cdn2.delay(Duration::from_millis(100));
+    for file in &files {
+        cdn2.put(&format!("/ura_data/{}", file.filename), fs::read(file.tempfile()?)?);
+    }
+    let limits = DownloadLimits { concurrency: 3, per_host: 8 };
+    run_test_pool(vec![Mirror::new(&cdn2.base_url)], files.clone(), limits, RetryPolicy::default()).await;
+    assert_eq!(cdn2.max_active(), 3);
+
+    for file in &files {
+        assert_eq!(fs::read_to_string(file.tempfile()?)?, file.filename.repeat(100));
+        fs::remove_file(file.tempfile()?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_download_retry() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+
+    let policy = RetryPolicy { attempts: 3, base: Duration::from_millis(10), max: Duration::from_millis(40) };
+    for retry in 0..8 {
+        let delay = policy.delay(retry);
+        assert!(delay >= policy.base.saturating_mul(1 << retry).min(policy.max) / 2);
+        assert!(delay <= policy.max);
+    }
+
+    let cdn = TestCdn::start().await;
+    let data = b"retry me ".repeat(20_000);
+    cdn.put("/ai_data/test_retry.bin", data.clone());
+    let mirrors = vec![Mirror::new(&cdn.base_url)];
+    let limits = DownloadLimits::default();
+
+    // 中途断开后自动重试并续传
+    cdn.cut("/ai_data/test_retry.bin", 30_000);
+    let file = DownloadFile::new("ai_data", "test_retry.bin");
+    let msgs = run_test_pool(mirrors.clone(), vec![file], limits, policy).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(fs::read("test_retry.bin.autoupdate")?, data);
+    assert_eq!(cdn.log(), ["200 /ai_data/test_retry.bin", "206 /ai_data/test_retry.bin"]);
+    fs::remove_file("test_retry.bin.autoupdate")?;
+
+    // 次数用完后明确报告失败
+    let missing = DownloadFile::new("ai_data", "test_missing.bin");
+    let msgs = run_test_pool(mirrors, vec![missing], limits, policy).await;
+    match msgs.last() {
+        Some(Message::OnDownloadFailed(f, err)) => {
+            assert_eq!(f.filename, "test_missing.bin");
+            assert!(err.contains("已重试2次"), "{err}");
+        }
+        _ => panic!("no failure message"),
+    }
+    assert_eq!(cdn.log().iter().filter(|l| *l == "404 /ai_data/test_missing.bin").count(), 3);
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_download_checks() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+
+    let cdn = TestCdn::start().await;
+    let data = b"cardDB ".repeat(1000);
+    cdn.put("/ai_data/test_checks.bin", data.clone());
+    let mirrors = vec![Mirror::new(&cdn.base_url)];
+
+    // 404页面不会写入临时文件
+    let missing = DownloadFile::new("ai_data", "test_checks_missing.bin");
+    let msgs = run_test_worker(mirrors.clone(), vec![missing]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadFailed(..))));
+    assert!(!fs::exists("test_checks_missing.bin.autoupdate")?);
+
+    // 大小和清单不符
+    let wrong = DownloadFile { size: Some(100), ..DownloadFile::new("ai_data", "test_checks.bin") };
+    let msgs = run_test_worker(mirrors.clone(), vec![wrong]).await;
+    match msgs.last() {
+        Some(Message::OnDownloadFailed(_, err)) => assert!(err.contains("大小错误"), "{err}"),
+        _ => panic!("size mismatch not detected"),
+    }
+    assert!(!fs::exists("test_checks.bin.autoupdate")?);
+
+    // 大小相符
+    let file = DownloadFile { size: Some(data.len() as u64), ..DownloadFile::new("ai_data", "test_checks.bin") };
+    let msgs = run_test_worker(mirrors.clone(), vec![file.clone()]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadCompleted(_))));
+    assert_eq!(fs::read("test_checks.bin.autoupdate")?, data);
+    // 结束时发送一次完整的进度
+    let progress: Vec<_> = msgs
+        .iter()
+        .filter_map(|m| match m {
+            Message::OnDownloadProgress(p) => Some(p.clone()),
+            _ => None,
+        })
+        .collect();
+    let last = progress.last().expect("no progress");
+    assert_eq!((last.downloaded, last.total), (data.len() as u64, Some(data.len() as u64)));
+    assert_eq!(last.fraction(), 1.0);
+
+    // 合并同一组件多个文件的进度
+    let part = DownloadProgress { downloaded: 100, total: Some(400), rate: 50.0, ..last.clone() };
+    let sum = DownloadProgress::sum([&part, &part]).expect("empty");
+    assert_eq!((sum.downloaded, sum.total, sum.rate), (200, Some(800), 100.0));
+    assert_eq!(sum.eta, Some(Duration::from_secs(6)));
+    let unknown = DownloadProgress { total: None, ..part.clone() };
+    assert_eq!(DownloadProgress::sum([&part, &unknown]).map(|p| p.total), Some(None));
+    // 还没开始的文件也计入总大小
+    let queued = DownloadProgress::queued(&DownloadFile { size: Some(400), ..DownloadFile::new("ai_data", "b.bin") });
+    let sum = DownloadProgress::sum([&part, &queued]).expect("empty");
+    assert_eq!((sum.downloaded, sum.total), (100, Some(800)));
+    assert_eq!(sum.eta, Some(Duration::from_secs(14)));
+
+    // 连接提前关闭时不交给校验，保留已下载的部分
+    cdn.cut("/ai_data/test_checks.bin", 1000);
+    let msgs = run_test_worker(mirrors, vec![file]).await;
+    assert!(matches!(msgs.last(), Some(Message::OnDownloadFailed(..))));
+    assert_eq!(fs::metadata("test_checks.bin.autoupdate")?.len(), 1000);
+    assert!(ResumeInfo::load("test_checks.bin.autoupdate").is_some());
+    fs::remove_file("test_checks.bin.autoupdate")?;
+    ResumeInfo::remove("test_checks.bin.autoupdate");
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! installer  
+//! 按组件类型选择安装方式
+use crate::utils::*;
+use crate::version_toml::*;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 旧版清单没有kind时，这个key对应更新工具自身
+pub const LEGACY_UPDATER_KEY: &str = "auto_update";
+
+/// 组件类型
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentKind {
+    /// 更新工具自身，filelist[0]是正在运行的exe
+    #[serde(rename = "self")]
+    SelfUpdate,
+    /// 其他可执行程序
+    App,
+    /// 数据文件
+    #[default]
+    Data,
+    /// 以完整压缩包发布
+    Package,
+}
+
+impl ComponentKind {
+    /// 该类型组件使用的安装方式
+    pub fn installer(self) -> Box<dyn Installer> {
+        match self {
+            ComponentKind::SelfUpdate => Box::new(SelfInstaller),
+            ComponentKind::App | ComponentKind::Data => Box::new(FileInstaller),
+            ComponentKind::Package => Box::new(PackageInstaller),
+        }
+    }
+}
+
+pub trait Installer {
+    /// 安装已校验的下载文件，每写入一个文件记录一条回执  
+    /// package表示这次下载的是完整压缩包而不是逐个文件
+    fn install(&self, info: &VersionInfo, package: bool, receipts: &mut HashMap<String, FileReceipt>) -> Result<()>;
+
+    /// 保存本地记录以后调用，失败时调用方恢复原来的记录
+    fn finish(&self, _info: &VersionInfo) -> Result<()> {
+        Ok(())
+    }
+
+    /// finish成功后是否需要重启更新工具
+    fn restart(&self) -> bool {
+        false
+    }
+}
+
+/// 逐个复制filelist中的文件
+struct FileInstaller;
+
+impl Installer for FileInstaller {
+    fn install(&self, info: &VersionInfo, _package: bool, receipts: &mut HashMap<String, FileReceipt>) -> Result<()> {
+        info.install(receipts)
+    }
+}
+
+/// 解压完整压缩包，压缩包下载失败改为逐个下载时按文件安装
+struct PackageInstaller;
+
+impl Installer for PackageInstaller {
+    fn install(&self, info: &VersionInfo, package: bool, receipts: &mut HashMap<String, FileReceipt>) -> Result<()> {
+        if package {
+            info.install_package(receipts)
+        } else {
+            info.install(receipts)
+        }
+    }
+}
+
+/// 保存本地记录后才替换正在运行的exe，替换成功后重启  
+/// 先替换再保存时，保存失败会留下新exe和旧记录
+struct SelfInstaller;
+
+impl Installer for SelfInstaller {
+    fn install(&self, info: &VersionInfo, _package: bool, receipts: &mut HashMap<String, FileReceipt>) -> Result<()> {
+        receipts.extend(info.self_receipts()?);
+        Ok(())
+    }
+
+    fn finish(&self, info: &VersionInfo) -> Result<()> {
+        let first = info.filelist.first().ok_or(anyhow!("{} 文件列表为空", info.name))?;
+        swap_self(&tempfile_path(first)?)
+    }
+
+    fn restart(&self) -> bool {
+        true
+    }
+}
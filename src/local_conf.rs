@@ -0,0 +1,222 @@
+//! local_conf  
+//! 本地version.toml的读写，写入是原子的，文件损坏时从备份恢复
+use crate::utils::*;
+use crate::validate::*;
+use crate::version_toml::*;
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::fs::{self, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const LOCAL_CONF: &str = "version.toml";
+/// 等待其他实例释放写锁的时间
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn backup_path(path: &str) -> String {
+    format!("{path}.bak")
+}
+
+/// 本地配置的写锁，drop时释放
+pub struct ConfLock {
+    _file: fs::File,
+}
+
+/// 获取path的写锁，防止多个更新工具同时写入
+pub fn lock_conf(path: &str, timeout: Duration) -> Result<ConfLock> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(format!("{path}.lock"))?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(ConfLock { _file: file }),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(anyhow!("另一个更新工具正在写入{path}，请稍后再试"));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+    }
+}
+
+/// 读取并解析path，不存在时返回None  
+/// 本地记录是自己写入的，TOML或字段解析失败说明文件被截断或损坏。
+/// 这里不做validate，旧版本写入的记录可能不符合现在更严格的规则，但不是损坏
+fn read_conf(path: &str) -> Result<Option<VersionToml>> {
+    if !fs::exists(path)? {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let table: toml::Table = toml::from_str(&content)?;
+    let mut conf = VersionToml::new();
+    for (key, value) in table {
+        let info = parse_component(&key, value).map_err(|e| anyhow!("本地记录{key}格式错误: {e}"))?;
+        conf.insert(key, info);
+    }
+    Ok(Some(conf))
+}
+
+/// 去掉不符合当前规则的记录，文件里保留原样
+fn usable(conf: VersionToml) -> VersionToml {
+    conf.into_iter()
+        .filter(|(key, info)| match info.validate() {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("本地记录 {key} 不符合当前规则，已忽略: {e}");
+                false
+            }
+        })
+        .collect()
+}
+
+/// 读取本地配置，文件损坏时改名为`.corrupt`并从备份恢复  
+/// 备份也不可用时视为没有本地记录
+pub fn load_conf(path: &str) -> Result<Option<VersionToml>> {
+    let err = match read_conf(path) {
+        Ok(conf) => return Ok(conf.map(usable)),
+        Err(e) => e,
+    };
+    warn!("{path} 已损坏: {err}，尝试从备份恢复");
+    let _lock = lock_conf(path, LOCK_TIMEOUT)?;
+    Ok(recover_conf(path)?.map(usable))
+}
+
+/// 把损坏的path改名为`.corrupt`并从备份恢复，调用前需要持有写锁
+fn recover_conf(path: &str) -> Result<Option<VersionToml>> {
+    fs::rename(path, format!("{path}.corrupt"))?;
+    let backup = backup_path(path);
+    match read_conf(&backup) {
+        Ok(Some(conf)) => {
+            write_atomic(path, &fs::read(&backup)?)?;
+            info!("已从 {backup} 恢复 {path}");
+            Ok(Some(conf))
+        }
+        _ => {
+            warn!("{backup} 不可用，视为没有本地记录");
+            Ok(None)
+        }
+    }
+}
+
+/// 在磁盘上最新的记录基础上修改，返回修改后可用的配置  
+/// 写入前把当前可用的配置备份为`.bak`
+fn modify_conf(path: &str, fallback: Option<VersionToml>, f: impl FnOnce(&mut VersionToml)) -> Result<VersionToml> {
+    let _lock = lock_conf(path, LOCK_TIMEOUT)?;
+    // 其他实例可能刚写过，以磁盘上的为准
+    // 只备份完整的文件，损坏的文件不能覆盖之前的备份
+    let mut conf = match read_conf(path) {
+        Ok(Some(conf)) => {
+            write_atomic(&backup_path(path), &fs::read(path)?)?;
+            conf
+        }
+        Ok(None) => fallback.unwrap_or_default(),
+        Err(e) => {
+            warn!("{path} 已损坏: {e}，尝试从备份恢复");
+            recover_conf(path)?.or(fallback).unwrap_or_default()
+        }
+    };
+    f(&mut conf);
+    write_atomic(path, toml::to_string_pretty(&conf)?.as_bytes())?;
+    Ok(usable(conf))
+}
+
+/// 写入一条记录
+pub fn update_conf(path: &str, key: &str, info: VersionInfo, fallback: Option<VersionToml>) -> Result<VersionToml> {
+    info!("update {path}: {key}");
+    modify_conf(path, fallback, |conf| {
+        conf.insert(key.to_string(), VersionInfo { key: key.to_string(), ..info });
+    })
+}
+
+/// 删除一条记录
+pub fn remove_conf(path: &str, key: &str, fallback: Option<VersionToml>) -> Result<VersionToml> {
+    info!("remove {path}: {key}");
+    modify_conf(path, fallback, |conf| {
+        conf.remove(key);
+    })
+}
+
+/// 获取本地配置文件
+pub fn get_local_conf() -> Result<Option<VersionToml>> {
+    load_conf(LOCAL_CONF)
+}
+
+#[cfg(test)]
+#[test]
+fn test_local_conf() -> Result<()> {
+    let path = "test_local_conf.toml";
+    for suffix in ["", ".bak", ".corrupt"] {
+        let _ = fs::remove_file(format!("{path}{suffix}"));
+    }
+    let info = |date: &str| VersionInfo {
+        name: "AI数据".to_string(),
+        date: date.to_string(),
+        filelist: vec!["db/cardDB.json".to_string()],
+        index: 2,
+        ..Default::default()
+    };
+    update_conf(path, "ai_data", info("2025-02-01 13:54:57"), None)?;
+    let conf = update_conf(path, "ura_data", info("2025-02-11 13:54:57"), None)?;
+    assert_eq!(conf.len(), 2);
+    assert!(!std::path::Path::new(&format!("{path}.tmp")).exists());
+    assert_eq!(remove_conf(path, "ura_data", None)?.len(), 1);
+    let conf = update_conf(path, "ura_data", info("2025-02-11 13:54:57"), None)?;
+    assert_eq!(conf.len(), 2);
+
+    // 写了一半的文件从备份恢复，备份里是上一次写入前的内容
+    fs::write(path, "[ai_data]\nname = \"AI")?;
+    let recovered = load_conf(path)?.expect("not recovered");
+    assert_eq!(recovered.len(), 1);
+    assert_eq!(recovered["ai_data"].key, "ai_data");
+    assert!(read_conf(path)?.is_some());
+    assert!(std::path::Path::new(&format!("{path}.corrupt")).exists());
+
+    // 在条目中间截断，仍是合法的TOML
+    update_conf(path, "ura_data", info("2025-02-12 13:54:57"), None)?;
+    let conf = update_conf(path, "ura_data", info("2025-02-12 13:54:57"), None)?;
+    assert_eq!(conf.len(), 2);
+    let content = fs::read_to_string(path)?;
+    let cut = content.find("[ura_data]").expect("no ura_data");
+    let cut = cut + content[cut..].find("date").expect("no date");
+    fs::write(path, &content[..cut])?;
+    assert!(read_conf(path).is_err());
+    // 写入时不把截断的文件备份，而是先从备份恢复
+    let conf = update_conf(path, "umaai", info("2025-02-13 13:54:57"), None)?;
+    assert_eq!(conf.len(), 3);
+    assert_eq!(conf["ura_data"].date, "2025-02-12 13:54:57");
+    assert_eq!(read_conf(&backup_path(path))?.map(|c| c.len()), Some(2));
+    fs::write(path, &content[..cut])?;
+    assert_eq!(load_conf(path)?.map(|c| c.len()), Some(2));
+
+    // 旧版本写入、不符合现在规则的记录不算损坏，忽略但保留在文件里
+    let _ = fs::remove_file(format!("{path}.corrupt"));
+    let mut content = fs::read_to_string(path)?;
+    content.push_str("\n[old_data]\nname = \"旧数据\"\ndate = \"2025-01-01 00:00:00\"\nindex = 4\nfilelist = [\"../old.json\"]\n");
+    fs::write(path, &content)?;
+    assert_eq!(load_conf(path)?.map(|c| c.len()), Some(2));
+    assert!(!std::path::Path::new(&format!("{path}.corrupt")).exists());
+    let conf = update_conf(path, "umaai", info("2025-02-14 13:54:57"), None)?;
+    assert!(!conf.contains_key("old_data"));
+    assert!(read_conf(path)?.expect("no conf").contains_key("old_data"));
+
+    // 备份也坏了时视为没有本地记录
+    fs::write(path, "[broken")?;
+    fs::write(backup_path(path), "[broken")?;
+    assert!(load_conf(path)?.is_none());
+
+    // 其他实例持有写锁
+    let lock = lock_conf(path, LOCK_TIMEOUT)?;
+    assert!(lock_conf(path, Duration::from_millis(200)).is_err());
+    drop(lock);
+    lock_conf(path, Duration::ZERO)?;
+
+    for suffix in ["", ".bak", ".corrupt", ".lock"] {
+        let _ = fs::remove_file(format!("{path}{suffix}"));
+    }
+    Ok(())
+}
@@ -1,10 +1,10 @@
 #![windows_subsystem = "windows"]
 use iced::widget::{
-    column, container, image, text, Column
+    button, column, container, image, markdown, pick_list, row, scrollable, text, Column
 };
 use iced::{window, Size};
 use iced::{
-    Top, Bottom, Center, Element, Fill,
+    Border, Bottom, Center, Color, Element, Fill,
     FillPortion, Subscription, Theme, Font, Settings, Task
 };
 use iced::advanced::image::Handle;
@@ -12,19 +12,34 @@ use iced::advanced::image::Handle;
 use futures_channel::mpsc::Sender;
 use std::default::Default;
 use std::collections::HashMap;
-use log::{info, error};
+use log::{info, error, warn};
 use tokio::runtime::Runtime;
 use rust_embed::Embed;
 
 mod modal;
+mod version;
 mod version_toml;
 mod version_widget;
 mod download;
 mod utils;
+mod settings;
+mod validate;
+mod plan;
+mod backup;
+mod installer;
+mod local_conf;
+mod remote_cache;
+#[cfg(test)]
+mod test_cdn;
 
 use utils::*;
+use settings::*;
 use modal::*;
 use version_toml::*;
+use plan::*;
+use backup::*;
+use installer::*;
+use local_conf::*;
 use version_widget::*;
 use download::*;
 
@@ -36,8 +51,7 @@ pub fn main() -> iced::Result {
     let icon = window::icon::from_file_data(
         &Res::get("umaai-sm.ico")
             .expect("Icon resource error")
-            .data
-            .to_vec(),
+            .data,
         None
     ).expect("Icon error");
     // app设定
@@ -48,6 +62,14 @@ pub fn main() -> iced::Result {
     };
     // 删除旧版
     let _ = remove_old();
+    // 更新服务器设置，命令行参数优先
+    let mut app_settings = AppSettings::load().unwrap_or_else(|e| {
+        error!("{e}");
+        AppSettings::default()
+    });
+    if let Err(e) = app_settings.apply_args(std::env::args().skip(1)) {
+        error!("{e}");
+    }
 
     iced::application("UmaAI 自动更新工具 0.1.3 250401", MainWindow::update, MainWindow::view)
         .subscription(MainWindow::subscription)
@@ -59,17 +81,29 @@ pub fn main() -> iced::Result {
             ..Default::default()
         })
         .resizable(false)
-        .run()
+        .run_with(move || (MainWindow::new(app_settings), Task::none()))
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Message {
     OnLoad,
-    OnLoadRemote(HashMap<String, VersionInfo>),
+    OnLoadRemote(RemoteConf, Channel),
+    OnSelectChannel(Channel),
     OnSetInfo(String),
+    /// 实际提供文件的服务器
+    OnSetMirror(String),
     OnClickUpdate(VersionWidget),
+    OnClickRollback(VersionWidget),
+    OnClickUninstall(VersionWidget),
+    OnClickNotes(VersionWidget),
+    /// 显示更新说明，标题和Markdown内容
+    OnShowNotes(String, String),
+    OnCloseNotes,
+    OnClickLink(markdown::Url),
+    OnDownloadProgress(DownloadProgress),
     OnDownloadCompleted(DownloadFile),
+    OnDownloadFailed(DownloadFile, String),
     OnListenerReady(Sender<Message>)
 }
 
@@ -90,17 +124,54 @@ struct MainWindow {
     pub info_text: String,
     /// 发送到下载线程的文件信息下行通道，rx端在下载线程里
     pub tx_file: Option<Sender<DownloadFile>>,
-    pub in_progress: HashMap<String, usize>
+    pub in_progress: HashMap<String, usize>,
+    /// 下载失败的组件和原因，重试时清除
+    pub failed: HashMap<String, String>,
+    /// 正在下载的组件 -> 文件名 -> 进度
+    pub progress: HashMap<String, HashMap<String, DownloadProgress>>,
+    pub settings: AppSettings,
+    /// 当前使用的更新服务器
+    pub mirror: Option<String>,
+    /// 前置组件更新完成后继续更新的组件
+    pub pending: Vec<String>,
+    /// 更新工具重启前没有完成的更新计划，加载远程清单后继续
+    pub resume: Vec<String>,
+    /// 正在显示的更新说明
+    pub notes: Option<(String, Vec<markdown::Item>)>
 }
 
 impl MainWindow {
+    pub fn new(settings: AppSettings) -> Self {
+        // 不是更新后重启时丢弃残留的计划
+        let mut resume = take_pending(PENDING_FILE);
+        if !settings.after_update {
+            resume.clear();
+        }
+        MainWindow {
+            settings,
+            resume,
+            ..Default::default()
+        }
+    }
+
     pub fn load(&mut self, version_data: VersionData) -> &mut Self {
         match version_data.pick() {
-            Some(data) => {
+            Some(_) => {
                 // 重新生成widgets 并按index排序
                 let mut widgets = vec![];
-                for k in data.keys() {
-                    widgets.push(VersionWidget::new(&version_data, k));
+                for k in &version_data.component_keys() {
+                    let mut w = VersionWidget::new(&version_data, k);
+                    w.failed = self.failed.get(k).cloned();
+                    w.progress = self.component_progress(k);
+                    if w.remote.is_some() {
+                        w.blocked = version_data.update_plan(k).err().map(|e| e.to_string());
+                    }
+                    if let Some(min) = version_data.updater_outdated(&updater_version()) {
+                        if w.kind() != ComponentKind::SelfUpdate {
+                            w.blocked = Some(format!("需要先把自动更新工具更新到 {min}"));
+                        }
+                    }
+                    widgets.push(w);
                 }
                 widgets.sort_by_key(|w| w.index);
                 self.version_data = version_data;
@@ -119,15 +190,18 @@ impl MainWindow {
             Message::OnLoad => {
                 // 获取本地版本数据
                 let local = get_local_conf()?;
-                let version_data = VersionData { local, remote: None };
+                let version_data = VersionData { local, ..Default::default() };
                 self.load(version_data);
                 self.info_text = "加载远程版本数据...".to_string();
+                let mirrors = self.settings.mirrors.clone();
+                let channel = self.settings.channel;
+                let require_signature = !self.settings.allow_unsigned_manifest;
                 Ok(Task::future(async move {
                     let runtime = Runtime::new().unwrap();
                     runtime.block_on(async move {
-                        match get_remote_conf().await {
+                        match get_remote_conf(&mirrors, channel, require_signature).await {
                             Ok(remote) => {
-                                Message::OnLoadRemote(remote)
+                                Message::OnLoadRemote(remote, channel)
                             }
                             Err(e) => {
                                 Message::OnSetInfo(e.to_string())
@@ -136,9 +210,78 @@ impl MainWindow {
                     })
                 }))
             }
-            Message::OnLoadRemote(remote) => {
-                self.load(VersionData { local: self.version_data.local.clone(), remote: Some(remote) });
-                self.info_text = "加载完成".to_string();
+            Message::OnLoadRemote(_, channel) if channel != self.settings.channel => {
+                // 加载期间切换了频道
+                Ok(Task::none())
+            }
+            Message::OnLoadRemote(RemoteConf { conf, mirror, stale_since }, channel) => {
+                let invalid = conf.invalid.len();
+                self.load(VersionData {
+                    local: self.version_data.local.clone(),
+                    remote: Some(conf.components),
+                    invalid: conf.invalid,
+                    min_updater_version: conf.min_updater_version,
+                    channel,
+                    stale_since,
+                });
+                self.info_text = if let Some(since) = &self.version_data.stale_since {
+                    format!("无法连接更新服务器，显示{since}缓存的清单")
+                } else if invalid > 0 {
+                    format!("加载完成，{invalid}个组件格式错误")
+                } else {
+                    "加载完成".to_string()
+                };
+                self.mirror = Some(mirror);
+                // 强制更新的组件直接开始下载
+                let forced = self.version_data.forced_plan(&updater_version(), |k| {
+                    self.widgets.iter().any(|w| w.key == k && w.needs_update().0)
+                });
+                // 更新工具无法更新到要求的版本，其他组件保持锁定
+                let mut plan = match forced {
+                    Ok(plan) => plan,
+                    Err(e) => return Ok(Task::done(Message::text(&e.to_string()))),
+                };
+                // 更新工具重启前没有完成的计划
+                for key in std::mem::take(&mut self.resume) {
+                    if !self.widgets.iter().any(|w| w.key == key && w.needs_update().0) {
+                        continue;
+                    }
+                    match self.version_data.update_plan(&key) {
+                        Ok(keys) => {
+                            for k in keys {
+                                if !plan.contains(&k) {
+                                    plan.push(k);
+                                }
+                            }
+                        }
+                        Err(e) => warn!("{key} 无法继续更新: {e}"),
+                    }
+                }
+                if !plan.is_empty() {
+                    info!("强制更新: {plan:?}");
+                    let first = plan.remove(0);
+                    self.pending = plan;
+                    return self.start_update(&first);
+                }
+                Ok(Task::none())
+            }
+            Message::OnSelectChannel(channel) => {
+                if channel == self.settings.channel {
+                    return Ok(Task::none());
+                }
+                if !self.in_progress.is_empty() {
+                    return Ok(Task::done(Message::text("正在更新，请稍后再切换频道")));
+                }
+                info!("切换频道: {}", channel.name());
+                self.settings.channel = channel;
+                self.settings.save_channel()?;
+                self.pending.clear();
+                self.failed.clear();
+                Ok(Task::done(Message::OnLoad))
+            }
+            Message::OnSetMirror(url) => {
+                info!("下载服务器: {url}");
+                self.mirror = Some(url);
                 Ok(Task::none())
             }
             Message::OnSetInfo(text) => {
@@ -147,56 +290,299 @@ impl MainWindow {
                 Ok(Task::none())
             }
             Message::OnClickUpdate(widget) => {
-                if let Some(remote) = &widget.remote {
-                    if let Some(tx_file) = &mut self.tx_file {
-                        for filename in &remote.filelist {
-                            tx_file.start_send(DownloadFile { key: widget.key.clone(), filename: filename.clone() }).unwrap();
+                // 先更新缺少的前置组件，完成后依次继续
+                let mut plan = self.version_data.update_plan(&widget.key)?;
+                let first = plan.remove(0);
+                self.pending = plan;
+                if !self.pending.is_empty() {
+                    info!("{} 需要先更新 {first}", widget.key);
+                }
+                self.start_update(&first)
+            }
+            Message::OnClickRollback(widget) => {
+                if self.in_progress.contains_key(&widget.key) {
+                    return Ok(Task::done(Message::text("正在更新，不能回滚")));
+                }
+                let dir = widget.local
+                    .as_ref()
+                    .and_then(|l| l.backup.clone())
+                    .ok_or(anyhow::anyhow!("{} 没有可用的备份", widget.name))?;
+                let old = restore(&widget.key, &dir)?;
+                let text = format!("已回滚 {} 到 {}", widget.name, old.version());
+                let kind = old.kind();
+                let first = old.filelist.first().cloned().unwrap_or_default();
+                self.version_data.save_local(&widget.key, old)?;
+                if kind == ComponentKind::SelfUpdate {
+                    // 替换失败时恢复原来的记录
+                    if let Err(e) = swap_self(&tempfile_path(&first)?) {
+                        if let Some(local) = widget.local {
+                            self.version_data.save_local(&widget.key, local)?;
                         }
-                        self.in_progress.insert(widget.key.clone(), remote.filelist.len());
+                        return Err(e);
                     }
+                    restart_self()?;
                 }
-                Ok(Task::done(Message::text(&format!("正在更新 {}", widget.name))))
+                self.load(self.version_data.clone());
+                Ok(Task::done(Message::text(&text)))
+            }
+            Message::OnClickUninstall(widget) => {
+                if self.in_progress.contains_key(&widget.key) {
+                    return Ok(Task::done(Message::text("正在更新，不能卸载")));
+                }
+                let local = widget.local
+                    .as_ref()
+                    .ok_or(anyhow::anyhow!("{} 没有安装", widget.name))?;
+                // 卸载前备份
+                let backup = snapshot(&widget.key, local)?;
+                let removed = local.uninstall()?;
+                self.version_data.remove_local(&widget.key)?;
+                self.load(self.version_data.clone());
+                let text = match backup {
+                    Some(dir) => format!("已卸载 {}，删除{}个文件，备份在 {dir}", widget.name, removed.len()),
+                    None => format!("已卸载 {}", widget.name),
+                };
+                Ok(Task::done(Message::text(&text)))
+            }
+            Message::OnClickNotes(widget) => {
+                let remote = widget.remote.ok_or(anyhow::anyhow!("未获取远程版本"))?;
+                let title = format!("{} {} 更新内容", widget.name, remote.version());
+                if let Some(notes) = remote.notes {
+                    return Ok(Task::done(Message::OnShowNotes(title, notes)));
+                }
+                let notes_url = remote.notes_url.ok_or(anyhow::anyhow!("{} 没有更新说明", widget.name))?;
+                let expected: FileHash = remote.notes_hash
+                    .ok_or(anyhow::anyhow!("{} 的更新说明没有Hash", widget.name))?
+                    .parse()?;
+                let mirrors = self.settings.mirrors.clone();
+                let channel = widget.channel;
+                Ok(Task::future(async move {
+                    let runtime = Runtime::new().unwrap();
+                    runtime.block_on(async move {
+                        match fetch_notes(&mirrors, channel, &widget.key, &notes_url, &expected).await {
+                            Ok(notes) => Message::OnShowNotes(title, notes),
+                            Err(e) => Message::OnSetInfo(e.to_string()),
+                        }
+                    })
+                }))
+            }
+            Message::OnShowNotes(title, notes) => {
+                self.notes = Some((title, markdown::parse(&notes).collect()));
+                Ok(Task::none())
+            }
+            Message::OnCloseNotes => {
+                self.notes = None;
+                Ok(Task::none())
+            }
+            Message::OnClickLink(url) => {
+                open_url(url.as_str())?;
+                Ok(Task::none())
             }
             Message::OnListenerReady(sender) => {
                 // listener已经启动，sender为下载线程使用的消息发动端
                 // 这时初始化下载线程，直接使用channel作为下载队列
                 let (tx_file, rx_file) = futures_channel::mpsc::channel(128);
                 self.tx_file = Some(tx_file);
-                let mut worker = DownloadWorker::new(sender, rx_file);
+                let mut worker = DownloadWorker::new(
+                    sender,
+                    rx_file,
+                    self.settings.mirrors.clone(),
+                    self.settings.download,
+                );
                 Ok(Task::perform(async move {
                     worker.run();
                 }, |_| { Message::text("listenerready") }))
             }
+            Message::OnDownloadProgress(p) => {
+                let key = p.key.clone();
+                // 组件已经结束时忽略迟到的进度
+                if self.in_progress.contains_key(&key) {
+                    self.progress.entry(key.clone()).or_default().insert(p.filename.clone(), p);
+                    self.refresh_progress(&key);
+                }
+                Ok(Task::none())
+            }
             Message::OnDownloadCompleted(d) => {
-                self.in_progress
-                    .entry(d.key.clone())
-                    .and_modify(|count| *count -= 1);
-                if self.in_progress[&d.key] == 0 {
+                // 同组件已有文件下载失败时不再安装
+                if self.settle(&d.key) && !self.failed.contains_key(&d.key) {
                     // 下载完成
                     let mut w = self.widgets
                         .clone()
                         .into_iter()
                         .find(|w| w.key == d.key)
                         .expect("widget not found");
-                    if w.verify() {
-                        self.version_data.update_and_save(&d.key)?;
-                        if d.key == "auto_update" {
-                            replace_self()?;
-                        } else {
-                            w.replace()?;
+                    let verified = if d.package { w.verify_package() } else { w.verify() };
+                    if let Err(e) = verified {
+                        self.pending.clear();
+                        let err = format!("{} 更新文件校验错误: {e}，请联系管理员", w.key);
+                        return Ok(Task::done(Message::text(&err)));
+                    }
+                    // 覆盖前备份当前安装的文件
+                    let backup = match &w.local {
+                        Some(local) => snapshot(&d.key, local)?,
+                        None => None,
+                    };
+                    let mut receipts = HashMap::new();
+                    let installed = w.replace(d.package, &mut receipts);
+                    // 安装失败时也保存已写入文件的回执
+                    self.version_data.update_and_save(&d.key, backup, receipts, installed.is_ok())?;
+                    self.load(self.version_data.clone());
+                    if let Err(e) = installed {
+                        self.pending.clear();
+                        return Ok(Task::done(Message::text(&format!("{} 安装失败: {e}", w.name))));
+                    }
+                    // 新版本不再包含的文件已在上面备份
+                    if let (Some(old), Some(new)) = (&w.local, &w.remote) {
+                        if let Err(e) = new.remove_orphans(old) {
+                            warn!("删除已移除的文件失败: {e}");
+                        }
+                    }
+                    // 重启后继续剩下的组件
+                    if w.kind() == ComponentKind::SelfUpdate {
+                        save_pending(PENDING_FILE, &self.pending)?;
+                    }
+                    // 更新工具自身在这里替换exe，替换失败时恢复原来的记录
+                    if let Err(e) = w.finish() {
+                        let _ = save_pending(PENDING_FILE, &[]);
+                        match &w.local {
+                            Some(local) => self.version_data.save_local(&d.key, local.clone())?,
+                            None => self.version_data.remove_local(&d.key)?,
                         }
                         self.load(self.version_data.clone());
-                    } else {
-                        let err = format!("{} 更新文件校验错误，请联系管理员", w.key);
-                        return Ok(Task::done(Message::text(&err)));
+                        self.pending.clear();
+                        return Ok(Task::done(Message::text(&format!("{} 安装失败: {e}", w.name))));
+                    }
+                    // exe已经替换，重启失败时不能再恢复记录
+                    if w.needs_restart() {
+                        if let Err(e) = restart_self() {
+                            let _ = save_pending(PENDING_FILE, &[]);
+                            self.pending.clear();
+                            return Ok(Task::done(Message::text(&format!("{} 已更新，但自动重启失败: {e}，请手动重启", w.name))));
+                        }
+                    }
+                    if !self.pending.is_empty() {
+                        let next = self.pending.remove(0);
+                        return self.start_update(&next);
                     }
                 }
                 Ok(Task::done(Message::text(&format!("更新完成 - {}", d.filename))))
             }
-            _ => Ok(Task::none())
+            Message::OnDownloadFailed(d, err) => {
+                let settled = self.settle(&d.key);
+                let remote = self.widgets
+                    .iter()
+                    .find(|w| w.key == d.key)
+                    .and_then(|w| w.remote.clone());
+                match remote {
+                    // 压缩包下载失败时改为逐个下载文件
+                    Some(remote) if d.package => {
+                        // 不再续传，删除下载了一部分的压缩包
+                        discard_partial(&d.tempfile()?);
+                        self.queue_download(&d.key, remote.download_files(&d.key));
+                        Ok(Task::done(Message::text(&format!("{} 压缩包下载失败，改为逐个下载文件", d.key))))
+                    }
+                    _ => {
+                        self.pending.clear();
+                        // 只记录第一个失败的文件
+                        if !self.failed.contains_key(&d.key) {
+                            self.set_failed(&d.key, Some(format!("{}: {err}", d.filename)));
+                        }
+                        let text = if settled {
+                            format!("下载失败: {err}")
+                        } else {
+                            format!("下载失败: {err}，等待其余文件结束")
+                        };
+                        Ok(Task::done(Message::text(&text)))
+                    }
+                }
+            }
+        }
+    }
+
+    /// 组件的一个文件下载结束，所有文件都结束时返回true
+    fn settle(&mut self, key: &str) -> bool {
+        match self.in_progress.get_mut(key) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                self.in_progress.remove(key);
+                self.progress.remove(key);
+                self.refresh_progress(key);
+                true
+            }
+            None => false,
         }
     }
 
+    /// 组件所有文件合并后的进度
+    fn component_progress(&self, key: &str) -> Option<DownloadProgress> {
+        self.progress.get(key).and_then(|files| DownloadProgress::sum(files.values()))
+    }
+
+    fn refresh_progress(&mut self, key: &str) {
+        let progress = self.component_progress(key);
+        if let Some(w) = self.widgets.iter_mut().find(|w| w.key == key) {
+            w.progress = progress;
+        }
+    }
+
+    /// 记录或清除组件的下载失败状态
+    fn set_failed(&mut self, key: &str, err: Option<String>) {
+        match &err {
+            Some(err) => self.failed.insert(key.to_string(), err.clone()),
+            None => self.failed.remove(key),
+        };
+        if let Some(w) = self.widgets.iter_mut().find(|w| w.key == key) {
+            w.failed = err;
+        }
+    }
+
+    /// 开始下载一个组件
+    fn start_update(&mut self, key: &str) -> anyhow::Result<Task<Message>> {
+        let w = self.widgets
+            .iter()
+            .find(|w| w.key == key)
+            .ok_or(anyhow::anyhow!("找不到组件 {key}"))?;
+        let name = w.name.clone();
+        let remote = w.remote.clone();
+        // 上次失败的其余文件还没结束
+        if self.in_progress.contains_key(key) {
+            return Ok(Task::done(Message::text(&format!("{name} 正在下载，请稍后"))));
+        }
+        self.set_failed(key, None);
+        if let Some(remote) = remote {
+            // 有完整压缩包时只下载一次压缩包
+            let files = match &remote.package {
+                Some(package) => vec![DownloadFile {
+                    size: remote.package_size,
+                    ..DownloadFile::package(key, package)
+                }],
+                None => remote.download_files(key),
+            };
+            self.queue_download(key, files);
+        }
+        Ok(Task::done(Message::text(&format!("正在更新 {name}"))))
+    }
+
+    /// 把文件放进下载队列并记录该组件还剩几个文件和各文件的进度
+    fn queue_download(&mut self, key: &str, files: Vec<DownloadFile>) {
+        if let Some(tx_file) = &mut self.tx_file {
+            self.in_progress.insert(key.to_string(), files.len());
+            // 按清单记录的大小先填上所有文件，合并的进度和剩余时间从一开始就包括还没开始的文件
+            let progress = files
+                .iter()
+                .map(|f| (f.filename.clone(), DownloadProgress::queued(f)))
+                .collect();
+            self.progress.insert(key.to_string(), progress);
+            for mut file in files {
+                file.channel = self.settings.channel;
+                tx_file.start_send(file).unwrap();
+            }
+        }
+        self.refresh_progress(key);
+    }
+
     fn update(&mut self, msg: Message) -> Task<Message> {
         match self.update_impl(msg) {
             Ok(task) => task,
@@ -215,7 +601,7 @@ impl MainWindow {
         ])
     }
 
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
         let background = container(
                 image(Handle::from_bytes(
                     Res::get("umaai-1.jpg")
@@ -235,9 +621,31 @@ impl MainWindow {
             .align_x(Center)
             .align_y(Bottom)
             .height(FillPortion(1));
-        let title_widget = text!("UmaAI 自动更新工具")
+        let mirror_text = text!("更新服务器: {}", self.mirror.as_deref().unwrap_or("无")).size(12);
+        let mirror_widget: Element<Message> = match &self.version_data.stale_since {
+            // 离线时使用的是缓存的清单
+            Some(since) => row![
+                mirror_text,
+                container(text!("离线 · 清单缓存于 {since}").size(12).color(Color::WHITE))
+                    .padding([0, 6])
+                    .style(|_| container::Style {
+                        background: Some(Color::from_rgb8(224, 128, 0).into()),
+                        ..Default::default()
+                    })
+            ].spacing(8).into(),
+            None => mirror_text.into(),
+        };
+        let title_text = text!("UmaAI 自动更新工具")
             .size(24)
             .align_x(Center)
+            .align_y(Center);
+        let channel_list = pick_list(
+            Channel::ALL,
+            Some(self.settings.channel),
+            Message::OnSelectChannel
+        ).text_size(12);
+        let title_widget = row![title_text, channel_list]
+            .spacing(20)
             .align_y(Center)
             .height(FillPortion(1));
         
@@ -246,7 +654,8 @@ impl MainWindow {
             Column::from_vec(widgets)
                 .width(Fill)
                 .height(FillPortion(6)),
-            info_widget
+            info_widget,
+            mirror_widget
         ].spacing(6)
         .align_x(Center)        
         .height(Fill);
@@ -259,6 +668,45 @@ impl MainWindow {
             .padding(6)
             .into();
         //element// .explain(Color::from_rgb(0.0, 1.0, 0.0))
+        let element = match &self.notes {
+            Some((title, items)) => Modal::new(element, notes_view(title, items))
+                .on_blur(Message::OnCloseNotes)
+                .into(),
+            None => element,
+        };
         Modal::new(background, element).into()
     }
 }
+
+/// 更新说明对话框
+fn notes_view<'a>(title: &'a str, items: &'a [markdown::Item]) -> Element<'a, Message> {
+    let notes = markdown::view(
+        items,
+        markdown::Settings::default(),
+        markdown::Style::from_palette(Theme::CatppuccinLatte.palette())
+    ).map(Message::OnClickLink);
+    let close = button(text("关闭").align_x(Center).width(Fill))
+        .style(button::primary)
+        .on_press(Message::OnCloseNotes)
+        .width(120);
+    container(
+        column![
+            text(title).size(20),
+            scrollable(notes).height(Fill),
+            close
+        ].spacing(12)
+        .align_x(Center)
+    ).width(520)
+    .height(400)
+    .padding(16)
+    .style(|_| container::Style {
+        background: Some(Color::WHITE.into()),
+        border: Border {
+            radius: 8.into(),
+            width: 1.0,
+            color: Color::from_rgb8(160, 160, 160)
+        },
+        ..Default::default()
+    })
+    .into()
+}
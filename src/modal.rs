@@ -4,9 +4,9 @@ use iced::advanced::renderer;
 use iced::advanced::widget::{self, Widget, Tree};
 use iced::advanced::{self, Clipboard, Shell};
 use iced::alignment::Alignment;
-use iced::{event, Border};
+use iced::event;
 use iced::mouse;
-use iced::{Color, Element, Event, Length, Point, Rectangle, Size, Vector};
+use iced::{Element, Event, Length, Rectangle, Size, Vector};
 
 /// A widget that centers a modal element over some base element
 pub struct Modal<'a, Message, Theme, Renderer> {
@@ -0,0 +1,290 @@
+//! plan  
+//! 根据requires计算组件的更新顺序
+use crate::installer::ComponentKind;
+use crate::version::parse_semver;
+use crate::version_toml::*;
+use anyhow::{anyhow, Result};
+use semver::{Version, VersionReq};
+use std::fs;
+
+/// 更新工具替换自身重启时，还没完成的更新计划保存在这里
+pub const PENDING_FILE: &str = "pending_update.txt";
+
+/// 正在运行的更新工具版本
+pub fn updater_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("invalid package version")
+}
+
+/// 保存重启后要继续更新的组件，为空时删除文件
+pub fn save_pending(path: &str, keys: &[String]) -> Result<()> {
+    if keys.is_empty() {
+        let _ = fs::remove_file(path);
+        return Ok(());
+    }
+    fs::write(path, keys.join("\n"))?;
+    Ok(())
+}
+
+/// 读取并删除重启前保存的更新计划
+pub fn take_pending(path: &str) -> Vec<String> {
+    let keys = fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect();
+    let _ = fs::remove_file(path);
+    keys
+}
+
+impl VersionData {
+    /// 更新工具自身的组件key，优先在远程清单里找
+    pub fn updater_key(&self) -> Option<&str> {
+        self.remote
+            .iter()
+            .chain(self.local.iter())
+            .flatten()
+            .find(|(_, info)| info.kind() == ComponentKind::SelfUpdate)
+            .map(|(k, _)| k.as_str())
+    }
+
+    /// 本地已安装的版本是否满足条件  
+    /// 更新工具以正在运行的exe为准，version.toml里的记录可能和实际不符
+    fn installed_satisfies(&self, key: &str, req: &VersionReq) -> bool {
+        if self.updater_key() == Some(key) {
+            return req.matches(&updater_version());
+        }
+        self.local
+            .as_ref()
+            .and_then(|local| local.get(key))
+            .is_some_and(|info| info.version().satisfies(req))
+    }
+
+    /// 正在运行的更新工具低于min_updater_version时返回要求的版本
+    pub fn updater_outdated(&self, running: &Version) -> Option<String> {
+        self.min_updater_version
+            .as_deref()
+            .filter(|v| parse_semver(v).is_some_and(|min| *running < min))
+            .map(|v| v.to_string())
+    }
+
+    /// 不需要点击就要更新的组件，已按前置条件排好顺序  
+    /// 更新工具版本过低时只更新它自己，其他组件要等重启以后。
+    /// 服务器上的更新工具也不满足要求，或者已经装过仍然过低时返回错误，避免每次启动都反复更新
+    pub fn forced_plan(&self, running: &Version, needs_update: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+        if let Some(min) = self.updater_outdated(running) {
+            let key = self.updater_key().ok_or(anyhow!("需要把自动更新工具更新到 {min}，但服务器上没有"))?;
+            let remote = self
+                .remote
+                .as_ref()
+                .and_then(|remote| remote.get(key))
+                .ok_or(anyhow!("需要把自动更新工具更新到 {min}，但服务器上没有"))?;
+            let version = remote.version();
+            let enough = version
+                .semver
+                .as_ref()
+                .zip(parse_semver(&min))
+                .is_some_and(|(v, min)| *v >= min);
+            if !enough {
+                return Err(anyhow!("需要把自动更新工具更新到 {min}，但服务器上的版本是 {version}，请联系管理员"));
+            }
+            if !needs_update(key) {
+                return Err(anyhow!("已安装服务器上的自动更新工具 {version}，但正在运行的版本 {running} 仍低于 {min}，请联系管理员"));
+            }
+            return self.update_plan(key);
+        }
+        let mut keys: Vec<_> = self.remote
+            .iter()
+            .flatten()
+            .filter(|(k, info)| info.mandatory && needs_update(k))
+            .map(|(k, info)| (info.index, k.clone()))
+            .collect();
+        keys.sort();
+        let mut plan: Vec<String> = vec![];
+        for (_, key) in keys {
+            for k in self.update_plan(&key).unwrap_or_default() {
+                if !plan.contains(&k) {
+                    plan.push(k);
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    /// 更新key之前需要依次更新的组件，最后一项是key本身  
+    /// 本地版本已满足的前置组件会跳过，远程版本也不满足时返回错误
+    pub fn update_plan(&self, key: &str) -> Result<Vec<String>> {
+        let mut plan = vec![];
+        self.visit(key, &mut vec![], &mut plan)?;
+        Ok(plan)
+    }
+
+    fn visit(&self, key: &str, path: &mut Vec<String>, plan: &mut Vec<String>) -> Result<()> {
+        if plan.iter().any(|k| k == key) {
+            return Ok(());
+        }
+        if path.iter().any(|k| k == key) {
+            return Err(anyhow!("循环依赖: {} -> {key}", path.join(" -> ")));
+        }
+        let info = self
+            .remote
+            .as_ref()
+            .and_then(|remote| remote.get(key))
+            .ok_or(anyhow!("服务器上没有组件 {key}"))?;
+        path.push(key.to_string());
+        let mut requires: Vec<_> = info.requires.iter().collect();
+        requires.sort();
+        for (dep, req) in requires {
+            let req = VersionReq::parse(req)?;
+            if self.installed_satisfies(dep, &req) {
+                continue;
+            }
+            let available = self
+                .remote
+                .as_ref()
+                .and_then(|remote| remote.get(dep))
+                .is_some_and(|d| d.version().satisfies(&req));
+            if !available {
+                return Err(anyhow!("{} 需要 {dep} {req}，服务器上没有满足条件的版本", info.name));
+            }
+            self.visit(dep, path, plan)?;
+        }
+        path.pop();
+        plan.push(key.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_update_plan() -> Result<()> {
+    use std::collections::HashMap;
+
+    let conf = |s: &str| -> Result<VersionToml> { Ok(toml::from_str(s)?) };
+    let local = conf(r#"
+[auto_update]
+name = "自动更新工具"
+date = "2025-02-11 13:54:57"
+filelist = ["uma-autoupdate.exe"]
+kind = "self"
+index = 6
+ver = "0.1.2"
+"#)?;
+    let remote = conf(r#"
+[auto_update]
+name = "自动更新工具"
+date = "2025-04-01 00:00:00"
+filelist = ["uma-autoupdate.exe"]
+kind = "self"
+index = 6
+ver = "0.1.3"
+
+[ai_data]
+name = "AI数据"
+date = "2025-04-01 00:00:00"
+filelist = ["db/cardDB.json"]
+index = 2
+requires = { auto_update = ">=0.1.3" }
+
+[ura_data]
+name = "小黑板数据"
+date = "2025-04-01 00:00:00"
+filelist = ["events.br"]
+index = 3
+requires = { ai_data = "*", auto_update = ">=0.2" }
+
+[loop_a]
+name = "a"
+date = "2025-04-01 00:00:00"
+filelist = ["a"]
+index = 7
+ver = "1.0.0"
+requires = { loop_b = "1" }
+
+[loop_b]
+name = "b"
+date = "2025-04-01 00:00:00"
+filelist = ["b"]
+index = 8
+ver = "1.0.0"
+requires = { loop_a = "1" }
+"#)?;
+    let mut data = VersionData { local: Some(local), remote: Some(remote), ..Default::default() };
+    assert_eq!(data.update_plan("auto_update")?, vec!["auto_update"]);
+    // 本地0.1.2不满足，需要先更新
+    assert_eq!(data.update_plan("ai_data")?, vec!["auto_update", "ai_data"]);
+    // 远程也不满足
+    let err = data.update_plan("ura_data").unwrap_err();
+    println!("{err}");
+    assert!(err.to_string().contains("auto_update"));
+    assert!(data.update_plan("loop_a").unwrap_err().to_string().contains("循环依赖"));
+    // 更新工具以正在运行的版本为准，不看本地记录
+    data.local.as_mut().unwrap().get_mut("auto_update").unwrap().ver = Some("99.0.0".to_string());
+    assert_eq!(data.update_plan("ai_data")?, vec!["auto_update", "ai_data"]);
+    let ai_data = data.remote.as_mut().unwrap().get_mut("ai_data").unwrap();
+    ai_data.requires = HashMap::from([("auto_update".to_string(), format!(">={}", updater_version()))]);
+    assert_eq!(data.update_plan("ai_data")?, vec!["ai_data"]);
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_pending_plan() -> Result<()> {
+    let path = "test_pending_update.txt";
+    save_pending(path, &["ai_data".to_string(), "ura_data".to_string()])?;
+    assert_eq!(take_pending(path), vec!["ai_data", "ura_data"]);
+    // 读取后删除，不会在下次启动时重复
+    assert!(take_pending(path).is_empty());
+    save_pending(path, &["ai_data".to_string()])?;
+    save_pending(path, &[])?;
+    assert!(!fs::exists(path)?);
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_forced_plan() -> Result<()> {
+    let remote: VersionToml = toml::from_str(r#"
+[auto_update]
+name = "自动更新工具"
+date = "2025-04-01 00:00:00"
+filelist = ["uma-autoupdate.exe"]
+kind = "self"
+index = 6
+ver = "0.1.3"
+
+[ai_data]
+name = "AI数据"
+date = "2025-04-01 00:00:00"
+filelist = ["db/cardDB.json"]
+index = 2
+mandatory = true
+
+[ura_data]
+name = "小黑板数据"
+date = "2025-04-01 00:00:00"
+filelist = ["events.br"]
+index = 3
+mandatory = true
+requires = { auto_update = ">=0.1.3" }
+"#)?;
+    let mut data = VersionData { remote: Some(remote), ..Default::default() };
+    assert_eq!(data.updater_key(), Some("auto_update"));
+    let running = Version::new(0, 1, 2);
+    assert_eq!(data.forced_plan(&running, |_| true)?, vec!["ai_data", "auto_update", "ura_data"]);
+    assert_eq!(data.forced_plan(&running, |k| k != "ai_data")?, vec!["auto_update", "ura_data"]);
+    // 更新工具过旧时先只更新自己
+    data.min_updater_version = Some("0.1.3".to_string());
+    assert_eq!(data.updater_outdated(&running).as_deref(), Some("0.1.3"));
+    assert_eq!(data.forced_plan(&running, |_| true)?, vec!["auto_update"]);
+    assert!(data.updater_outdated(&Version::new(0, 1, 3)).is_none());
+    // 已经装了服务器上的版本，运行的仍然过旧时不再反复更新
+    let err = data.forced_plan(&running, |_| false).unwrap_err();
+    println!("{err}");
+    assert!(err.to_string().contains("联系管理员"));
+    // 服务器上的版本也不满足要求
+    data.min_updater_version = Some("0.2".to_string());
+    let err = data.forced_plan(&running, |_| true).unwrap_err();
+    assert!(err.to_string().contains("0.1.3"));
+    Ok(())
+}
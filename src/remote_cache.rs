@@ -0,0 +1,93 @@
+//! remote_cache  
+//! 缓存最近一次获取的远程清单，服务器都不可用时离线使用
+use crate::settings::Channel;
+use crate::utils::*;
+use crate::version::DATE_FORMAT;
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+pub const CACHE_DIR: &str = "cache";
+const CONTENT: &str = "version.toml";
+const SIGNATURE: &str = "version.toml.sig";
+const META: &str = "meta.toml";
+
+/// 缓存的来源和条件请求用的响应头
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct CacheMeta {
+    /// 提供清单的服务器
+    pub mirror: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// 最近一次向服务器确认的时间
+    pub fetched: String,
+}
+
+impl CacheMeta {
+    pub fn new(mirror: &str, etag: Option<String>, last_modified: Option<String>) -> Self {
+        CacheMeta { mirror: mirror.to_string(), etag, last_modified, fetched: now() }
+    }
+}
+
+/// 缓存的清单原文和签名，使用前要重新校验签名
+#[derive(Clone, Debug, Default)]
+pub struct CachedManifest {
+    pub content: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub meta: CacheMeta,
+}
+
+/// 单个频道的清单缓存目录
+#[derive(Clone, Debug)]
+pub struct ManifestCache {
+    dir: PathBuf,
+}
+
+impl ManifestCache {
+    pub fn new(channel: Channel) -> Self {
+        Self::at(PathBuf::from(CACHE_DIR).join(channel.name()))
+    }
+
+    pub fn at(dir: impl Into<PathBuf>) -> Self {
+        ManifestCache { dir: dir.into() }
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir.join(name).to_string_lossy().to_string()
+    }
+
+    /// 读取缓存，不完整或损坏时返回None
+    pub fn load(&self) -> Option<CachedManifest> {
+        let meta = fs::read_to_string(self.path(META)).ok()?;
+        Some(CachedManifest {
+            content: fs::read(self.path(CONTENT)).ok()?,
+            signature: fs::read(self.path(SIGNATURE)).ok()?,
+            meta: toml::from_str(&meta).ok()?,
+        })
+    }
+
+    /// 保存新获取的清单  
+    /// meta最后写入，中途失败时load不会读到不完整的缓存
+    pub fn save(&self, content: &[u8], signature: &[u8], meta: &CacheMeta) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let _ = fs::remove_file(self.path(META));
+        write_atomic(&self.path(CONTENT), content)?;
+        write_atomic(&self.path(SIGNATURE), signature)?;
+        write_atomic(&self.path(META), toml::to_string_pretty(meta)?.as_bytes())
+    }
+
+    /// 服务器确认缓存仍是最新时更新确认时间
+    pub fn touch(&self, mirror: &str) -> Result<()> {
+        let Some(cached) = self.load() else {
+            return Ok(());
+        };
+        let meta = CacheMeta { mirror: mirror.to_string(), fetched: now(), ..cached.meta };
+        write_atomic(&self.path(META), toml::to_string_pretty(&meta)?.as_bytes())
+    }
+}
+
+fn now() -> String {
+    Local::now().format(DATE_FORMAT).to_string()
+}
@@ -0,0 +1,276 @@
+//! settings  
+//! 更新工具自身的设置，保存在settings.toml，可以被命令行参数覆盖
+use anyhow::{anyhow, Result};
+use log::info;
+use reqwest::header::REFERER;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::{fmt, fs};
+
+pub const SETTINGS_FILE: &str = "settings.toml";
+
+/// 更新服务器
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Mirror {
+    /// 服务器根目录，下面是version.toml和各组件目录
+    pub url: String,
+    /// 防盗链需要的Referer，可选
+    pub referer: Option<String>,
+}
+
+impl Mirror {
+    pub fn new(url: &str) -> Self {
+        Mirror {
+            url: url.trim_end_matches('/').to_string(),
+            referer: None,
+        }
+    }
+
+    /// 服务器的host，解析失败时使用完整url
+    pub fn host(&self) -> String {
+        reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_else(|| self.url.clone())
+    }
+
+    /// 带上Referer的GET请求
+    pub fn get(&self, cli: &Client, url: &str) -> RequestBuilder {
+        let req = cli.get(url);
+        match &self.referer {
+            Some(referer) => req.header(REFERER, referer),
+            None => req,
+        }
+    }
+}
+
+/// 发布频道，每个频道有自己的清单和文件目录
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 3] = [Channel::Stable, Channel::Beta, Channel::Nightly];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Beta => "beta",
+            Channel::Nightly => "nightly",
+        }
+    }
+
+    /// 频道在服务器上的目录，stable沿用根目录
+    pub fn dir(&self, mirror: &Mirror) -> String {
+        match self {
+            Channel::Stable => mirror.url.clone(),
+            _ => format!("{}/{}", mirror.url, self.name()),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Channel::Stable => "稳定版",
+            Channel::Beta => "测试版",
+            Channel::Nightly => "每日构建",
+        };
+        write!(f, "{text}")
+    }
+}
+
+impl std::str::FromStr for Channel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Channel::ALL
+            .into_iter()
+            .find(|c| c.name() == s)
+            .ok_or(anyhow!("未知的频道: {s}"))
+    }
+}
+
+/// 同时下载的文件数
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DownloadLimits {
+    /// 总共同时下载的文件数
+    pub concurrency: usize,
+    /// 同一台服务器同时下载的文件数
+    pub per_host: usize,
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        DownloadLimits { concurrency: 4, per_host: 2 }
+    }
+}
+
+fn default_mirrors() -> Vec<Mirror> {
+    vec![Mirror {
+        url: "https://cdn2.viktorlab.cn/uma".to_string(),
+        referer: Some("https://viktorlab.cn".to_string()),
+    }]
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AppSettings {
+    /// 更新服务器列表，按顺序尝试
+    #[serde(default = "default_mirrors")]
+    pub mirrors: Vec<Mirror>,
+    /// 发布频道
+    #[serde(default)]
+    pub channel: Channel,
+    /// 并发下载限制
+    #[serde(default)]
+    pub download: DownloadLimits,
+    /// 接受没有version.toml.sig的清单，默认拒绝  
+    /// 只用于服务器还没发布签名的过渡期，需要在settings.toml里显式开启
+    #[serde(default)]
+    pub allow_unsigned_manifest: bool,
+    /// 更新工具替换自身后重启，需要继续之前的更新计划，只来自命令行
+    #[serde(skip)]
+    pub after_update: bool,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            mirrors: default_mirrors(),
+            channel: Channel::default(),
+            download: DownloadLimits::default(),
+            allow_unsigned_manifest: false,
+            after_update: false,
+        }
+    }
+}
+
+impl AppSettings {
+    /// 读取settings.toml，不存在时使用默认设置
+    pub fn load() -> Result<Self> {
+        let mut ret = if Path::new(SETTINGS_FILE).exists() {
+            let content = fs::read_to_string(SETTINGS_FILE)?;
+            toml::from_str(&content).map_err(|e| anyhow!("{SETTINGS_FILE}格式错误: {e}"))?
+        } else {
+            AppSettings::default()
+        };
+        if ret.mirrors.is_empty() {
+            ret.mirrors = default_mirrors();
+        }
+        Ok(ret)
+    }
+
+    /// 把频道写回settings.toml，文件里的其他设置保持不变
+    pub fn save_channel(&self) -> Result<()> {
+        let mut table: toml::Table = if Path::new(SETTINGS_FILE).exists() {
+            toml::from_str(&fs::read_to_string(SETTINGS_FILE)?)?
+        } else {
+            toml::Table::new()
+        };
+        table.insert("channel".to_string(), self.channel.name().into());
+        fs::write(SETTINGS_FILE, toml::to_string_pretty(&table)?)?;
+        Ok(())
+    }
+
+    /// 应用命令行参数  
+    /// `--mirror <url>[,<referer>]` 可以重复，给出时替换设置文件里的服务器列表，
+    /// 没有给出referer时沿用同一主机已配置的referer  
+    /// `--channel <stable|beta|nightly>` 只对本次运行有效  
+    /// `--after-update` 由更新工具重启时传入
+    pub fn apply_args(&mut self, args: impl IntoIterator<Item = String>) -> Result<()> {
+        let mut mirrors = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--mirror" {
+                let url = args.next().ok_or(anyhow!("--mirror 缺少参数"))?;
+                mirrors.push(self.mirror_arg(&url));
+            } else if let Some(url) = arg.strip_prefix("--mirror=") {
+                mirrors.push(self.mirror_arg(url));
+            } else if arg == "--channel" {
+                self.channel = args.next().ok_or(anyhow!("--channel 缺少参数"))?.parse()?;
+            } else if let Some(channel) = arg.strip_prefix("--channel=") {
+                self.channel = channel.parse()?;
+            } else if arg == "--after-update" {
+                self.after_update = true;
+            }
+        }
+        if !mirrors.is_empty() {
+            info!("使用命令行指定的服务器: {mirrors:?}");
+            self.mirrors = mirrors;
+        }
+        Ok(())
+    }
+
+    /// 解析`--mirror`的参数`url`或`url,referer`
+    fn mirror_arg(&self, arg: &str) -> Mirror {
+        let (url, referer) = match arg.split_once(',') {
+            Some((url, referer)) => (url, Some(referer.trim()).filter(|r| !r.is_empty())),
+            None => (arg, None),
+        };
+        let mirror = Mirror::new(url.trim());
+        let referer = referer.map(|r| r.to_string()).or_else(|| {
+            let host = mirror.host();
+            self.mirrors
+                .iter()
+                .chain(&default_mirrors())
+                .find(|m| m.host() == host)
+                .and_then(|m| m.referer.clone())
+        });
+        Mirror { referer, ..mirror }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_settings_args() -> Result<()> {
+    let mut settings: AppSettings = toml::from_str(
+        "[[mirrors]]\nurl = \"https://a.example/uma\"\n\n[[mirrors]]\nurl = \"https://b.example/uma\"\nreferer = \"https://b.example\"\n",
+    )?;
+    assert_eq!(settings.mirrors.len(), 2);
+    assert_eq!(settings.mirrors[1].referer.as_deref(), Some("https://b.example"));
+
+    assert_eq!(settings.channel, Channel::Stable);
+    assert_eq!(settings.download, DownloadLimits::default());
+    // 默认拒绝没有签名的清单
+    assert!(!settings.allow_unsigned_manifest);
+    let limits: AppSettings = toml::from_str("[download]\nconcurrency = 8\n")?;
+    assert_eq!(limits.download, DownloadLimits { concurrency: 8, per_host: 2 });
+
+    let args = ["uma-autoupdate.exe", "--mirror", "http://127.0.0.1:8000/", "--mirror=http://c.example/uma", "--channel", "beta"];
+    settings.apply_args(args.map(String::from))?;
+    assert_eq!(settings.channel, Channel::Beta);
+    assert_eq!(Channel::Beta.dir(&settings.mirrors[1]), "http://c.example/uma/beta");
+    assert_eq!(Channel::Stable.dir(&settings.mirrors[1]), "http://c.example/uma");
+    assert_eq!(
+        settings.mirrors,
+        vec![Mirror::new("http://127.0.0.1:8000"), Mirror::new("http://c.example/uma")]
+    );
+    assert!(settings.mirrors.iter().all(|m| m.referer.is_none()));
+
+    // 指定referer，或沿用同一主机已配置的referer
+    let mut settings: AppSettings = toml::from_str(
+        "[[mirrors]]\nurl = \"https://b.example/uma\"\nreferer = \"https://b.example\"\n",
+    )?;
+    let args = [
+        "--mirror=http://c.example/uma,https://c.example",
+        "--mirror=https://b.example/uma2",
+        "--mirror=https://cdn2.viktorlab.cn/test",
+    ];
+    settings.apply_args(args.map(String::from))?;
+    let referers: Vec<_> = settings.mirrors.iter().map(|m| m.referer.as_deref()).collect();
+    assert_eq!(referers, [Some("https://c.example"), Some("https://b.example"), Some("https://viktorlab.cn")]);
+    assert_eq!(settings.mirrors[0].url, "http://c.example/uma");
+    assert!(!settings.after_update);
+    settings.apply_args(["--after-update".to_string()])?;
+    assert!(settings.after_update);
+    assert!(settings.apply_args(["--mirror".to_string()]).is_err());
+    assert!(settings.apply_args(["--channel=alpha".to_string()]).is_err());
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! test_cdn  
+//! 测试用的本地CDN，按路径返回预先放入的文件
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+type Files = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+type Log = Arc<Mutex<Vec<String>>>;
+
+/// 模拟服务器行为的开关
+#[derive(Default)]
+struct Options {
+    /// 忽略Range请求头，总是返回完整文件
+    ignore_ranges: bool,
+    /// 路径 -> 发送多少字节后断开，只生效一次
+    cut: HashMap<String, usize>,
+    /// 发送响应前等待的时间
+    delay: Duration,
+}
+
+/// 同时处理的请求数
+#[derive(Default)]
+struct Active {
+    now: usize,
+    max: usize,
+}
+
+#[derive(Clone, Default)]
+struct Server {
+    files: Files,
+    log: Log,
+    options: Arc<Mutex<Options>>,
+    active: Arc<Mutex<Active>>,
+}
+
+pub struct TestCdn {
+    /// 形如`http://127.0.0.1:端口`
+    pub base_url: String,
+    server: Server,
+}
+
+impl TestCdn {
+    /// 在随机端口启动，需要在tokio运行时里调用
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server::default();
+        let shared = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, shared.clone()));
+            }
+        });
+        TestCdn { base_url, server }
+    }
+
+    /// 放入文件，path以`/`开头
+    pub fn put(&self, path: &str, body: impl Into<Vec<u8>>) {
+        self.server.files.lock().unwrap().insert(path.to_string(), body.into());
+    }
+
+    /// 已处理的请求，形如`304 /version.toml`
+    pub fn log(&self) -> Vec<String> {
+        self.server.log.lock().unwrap().clone()
+    }
+
+    /// 下一次请求path时只发送前n字节就断开
+    pub fn cut(&self, path: &str, n: usize) {
+        self.server.options.lock().unwrap().cut.insert(path.to_string(), n);
+    }
+
+    pub fn ignore_ranges(&self, ignore: bool) {
+        self.server.options.lock().unwrap().ignore_ranges = ignore;
+    }
+
+    pub fn delay(&self, delay: Duration) {
+        self.server.options.lock().unwrap().delay = delay;
+    }
+
+    /// 同时处理的最大请求数
+    pub fn max_active(&self) -> usize {
+        self.server.active.lock().unwrap().max
+    }
+}
+
+/// 请求头的值，不区分名字大小写
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        k.trim().eq_ignore_ascii_case(name).then(|| v.trim())
+    })
+}
+
+/// `bytes=N-`形式的起始位置
+fn range_start(request: &str) -> Option<usize> {
+    header(request, "Range")?
+        .strip_prefix("bytes=")?
+        .strip_suffix('-')?
+        .parse()
+        .ok()
+}
+
+async fn serve(mut stream: TcpStream, server: Server) {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+    let request = String::from_utf8_lossy(&buf).to_string();
+    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+    let body = server.files.lock().unwrap().get(&path).cloned();
+    let (ignore_ranges, cut, delay) = {
+        let mut options = server.options.lock().unwrap();
+        (options.ignore_ranges, options.cut.remove(&path), options.delay)
+    };
+    // ETag取内容的blake3
+    let etag = body.as_ref().map(|b| format!("\"{}\"", blake3::hash(b).to_hex()));
+    // If-Range不符时返回完整文件
+    let range = range_start(&request)
+        .filter(|_| !ignore_ranges)
+        .filter(|_| header(&request, "If-Range").is_none_or(|v| Some(v) == etag.as_deref()));
+    let mut extra = String::new();
+    let (status, body) = match body {
+        Some(_) if etag.as_deref() == header(&request, "If-None-Match") => ("304 Not Modified", vec![]),
+        Some(body) => match range {
+            Some(start) if start < body.len() => {
+                extra = format!("Content-Range: bytes {start}-{}/{}\r\n", body.len() - 1, body.len());
+                ("206 Partial Content", body[start..].to_vec())
+            }
+            Some(_) => {
+                extra = format!("Content-Range: bytes */{}\r\n", body.len());
+                ("416 Range Not Satisfiable", vec![])
+            }
+            None => ("200 OK", body),
+        },
+        None => ("404 Not Found", b"not found".to_vec()),
+    };
+    server.log.lock().unwrap().push(format!("{} {path}", &status[..3]));
+    let etag = etag.map(|e| format!("ETag: {e}\r\n")).unwrap_or_default();
+    let header = format!(
+        "HTTP/1.1 {status}\r\n{etag}{extra}Accept-Ranges: bytes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    {
+        let mut active = server.active.lock().unwrap();
+        active.now += 1;
+        active.max = active.max.max(active.now);
+    }
+    tokio::time::sleep(delay).await;
+    let _ = stream.write_all(header.as_bytes()).await;
+    let body = match cut {
+        Some(n) => &body[..n.min(body.len())],
+        None => &body[..],
+    };
+    let _ = stream.write_all(body).await;
+    server.active.lock().unwrap().now -= 1;
+}
@@ -1,7 +1,13 @@
 use anyhow::{anyhow, Result};
-use std::{env, fs, process};
+use std::{env, fmt, fs, process};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use ed25519_dalek::{Signature, VerifyingKey};
 use log::info;
 use native_dialog::{MessageType, MessageDialog};
 use iced::Task;
@@ -22,6 +28,7 @@ pub fn init_logger() -> Result<()> {
 }
 
 /// 返回为iced的异步任务
+#[allow(dead_code)]
 pub fn msgbox(text: &str) -> Task<Message> {
     let t = text.to_string();
     Task::perform(async move {
@@ -39,6 +46,15 @@ pub fn to_hex(bytes: &[u8]) -> String {
     bytes.iter()
         .fold(String::new(), |s, byte| s + &format!("{:02x}", byte))
 }
+pub fn from_hex(s: &str) -> Result<Vec<u8>> {
+    if !s.is_ascii() || !s.len().is_multiple_of(2) {
+        return Err(anyhow!("十六进制格式错误"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| anyhow!("十六进制格式错误")))
+        .collect()
+}
 pub fn get_exe_name() -> Result<String> {
     let exe_path = env::current_exe()?;
     // 把exe_path转为相对路径。中文路径可能有问题
@@ -48,18 +64,46 @@ pub fn get_exe_name() -> Result<String> {
         .replace(&cwd.to_string_lossy().to_string(), ".")
     )
 }
-pub fn replace_self() -> Result<()> {
+/// 用下载的新exe替换正在运行的exe，旧exe改名为.old，下次启动时删除
+pub fn swap_self(tempfile: &str) -> Result<()> {
     let exe_name = get_exe_name()?;
     info!("Replacing {exe_name}");
     let old_name = format!("{exe_name}.old");
     fs::rename(&exe_name, &old_name)?;
-    fs::rename("uma-autoupdate.exe.autoupdate", &exe_name)?;
+    if let Err(e) = fs::rename(tempfile, &exe_name) {
+        fs::rename(&old_name, &exe_name)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// 启动新exe并退出，新exe会继续重启前保存的更新计划
+pub fn restart_self() -> Result<()> {
+    let exe_name = get_exe_name()?;
     let _ = Command::new("cmd")
-        .args(["/C", "start", &exe_name])
+        .args(["/C", "start", &exe_name, "--after-update"])
         .spawn()?;
     process::exit(0);
 }
 
+/// 检查链接，只允许http和https
+pub fn check_link(url: &str) -> Result<reqwest::Url> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("链接格式错误: {url}: {e}"))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => Err(anyhow!("不支持的链接类型{scheme}: {url}")),
+    }
+}
+
+/// 用默认浏览器打开链接  
+/// 通过ShellExecute打开，不经过cmd，链接里的`&`、`|`不会被当作命令
+pub fn open_url(url: &str) -> Result<()> {
+    let url = check_link(url)?;
+    info!("Open {url}");
+    open::that_detached(url.as_str())?;
+    Ok(())
+}
+
 pub fn remove_old() -> Result<()> {
     let exe_path = env::current_exe()?;
     // 把exe_path转为相对路径。中文路径可能有问题
@@ -75,12 +119,469 @@ pub fn remove_old() -> Result<()> {
     Ok(())
 }
 
-pub fn get_file_sha1(filename: &str) -> Result<String> {
-    let contents = std::fs::read(&filename)?;
-    let mut hasher = Sha1::new();
-    hasher.update(&contents);
-    let result = hasher.finalize();
-    let result_text = to_hex(&result);
-    info!("File: {filename}, SHA1: {result_text}");
-    Ok(result_text)
-}
\ No newline at end of file
+/// 规范化清单里的相对路径，统一用`/`分隔，去掉`.`和空段  
+/// 绝对路径、盘符和`..`都会被拒绝
+pub fn normalize_relative_path(path: &str) -> Result<String> {
+    let has_prefix = path.starts_with(['/', '\\']) || path.contains([':', '\0']);
+    let parts: Vec<_> = path
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    let all_normal = parts
+        .iter()
+        .all(|c| matches!(Path::new(c).components().next(), Some(Component::Normal(_))));
+    if has_prefix || parts.is_empty() || !all_normal {
+        Err(anyhow!("非法路径: {path}"))
+    } else {
+        Ok(parts.join("/"))
+    }
+}
+
+/// 检查清单里的相对路径，不能是绝对路径，也不能包含`..`
+pub fn check_relative_path(path: &str) -> Result<()> {
+    normalize_relative_path(path).map(|_| ())
+}
+
+/// 清单里的相对路径在root下的实际路径
+pub fn confine_path(root: &str, path: &str) -> Result<PathBuf> {
+    Ok(Path::new(root).join(normalize_relative_path(path)?))
+}
+
+/// 创建target的上级目录，并确认经过符号链接以后仍在root下
+pub fn create_parent_inside(root: &str, target: &Path) -> Result<()> {
+    let Some(parent) = target.parent() else {
+        return Ok(());
+    };
+    fs::create_dir_all(parent)?;
+    let root = fs::canonicalize(root)?;
+    if !fs::canonicalize(parent)?.starts_with(&root) {
+        return Err(anyhow!("{target:?} 不在安装目录 {root:?} 下"));
+    }
+    Ok(())
+}
+
+/// 先写入临时文件再改名，中途崩溃不会留下写了一半的文件
+pub fn write_atomic(path: &str, content: &[u8]) -> Result<()> {
+    let tmp = format!("{path}.tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// 下载临时文件的路径，限制在工作目录下
+pub fn tempfile_path(filename: &str) -> Result<String> {
+    Ok(format!("{}.autoupdate", normalize_relative_path(filename)?))
+}
+
+/// install_path支持的变量
+const PATH_VARS: [&str; 9] = [
+    "%localappdata%",
+    "%appdata%",
+    "%userprofile%",
+    "~",
+    "$XDG_DATA_HOME",
+    "$XDG_CONFIG_HOME",
+    "$HOME",
+    "{key}",
+    "{channel}",
+];
+
+/// 可以作为install_path开头的目录变量
+const BASE_VARS: [&str; 7] = [
+    "%localappdata%",
+    "%appdata%",
+    "%userprofile%",
+    "~",
+    "$XDG_DATA_HOME",
+    "$XDG_CONFIG_HOME",
+    "$HOME",
+];
+
+/// 第一个非空的环境变量
+fn env_first(env: &impl Fn(&str) -> Option<String>, names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| env(name).filter(|v| !v.is_empty()))
+}
+
+fn home_dir(env: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    env_first(env, &["USERPROFILE", "HOME"]).ok_or(anyhow!("找不到用户目录，USERPROFILE和HOME都未设置"))
+}
+
+/// Windows和Linux上对应的目录互为后备，同一份清单在两边都能用
+fn resolve_path_var(var: &str, key: &str, channel: &str, env: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let or_home = |names: &[&str], sub: &str| match env_first(env, names) {
+        Some(dir) => Ok(dir),
+        None => Ok(format!("{}/{sub}", home_dir(env)?)),
+    };
+    match var {
+        "%localappdata%" | "$XDG_DATA_HOME" => or_home(&["LOCALAPPDATA", "XDG_DATA_HOME"], ".local/share"),
+        "%appdata%" | "$XDG_CONFIG_HOME" => or_home(&["APPDATA", "XDG_CONFIG_HOME"], ".config"),
+        "%userprofile%" | "~" | "$HOME" => home_dir(env),
+        "{key}" if key.is_empty() => Err(anyhow!("{{key}}只能用于清单中的组件")),
+        "{key}" => Ok(key.to_string()),
+        "{channel}" => Ok(channel.to_string()),
+        _ => Err(anyhow!("install_path中有未知变量 {var}，支持的变量: {}", PATH_VARS.join(" "))),
+    }
+}
+
+/// 切出模板中的变量，返回(变量, 变量后的剩余部分)  
+/// `%...%`不区分大小写，`~`只能出现在开头
+fn next_path_var(rest: &str, at_start: bool) -> Result<Option<(String, &str)>> {
+    let closed = |open: char, close: char| -> Result<(String, &str)> {
+        let end = rest[1..]
+            .find(close)
+            .ok_or(anyhow!("install_path中的{open}没有闭合: {rest}"))?;
+        Ok((rest[..end + 2].to_string(), &rest[end + 2..]))
+    };
+    let ret = match rest.chars().next() {
+        Some('%') => closed('%', '%').map(|(v, r)| (v.to_lowercase(), r))?,
+        Some('{') => closed('{', '}')?,
+        Some('$') => {
+            let end = rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .map_or(rest.len(), |i| i + 1);
+            (rest[..end].to_string(), &rest[end..])
+        }
+        Some('~') if at_start && (rest.len() == 1 || rest[1..].starts_with(['/', '\\'])) => {
+            ("~".to_string(), &rest[1..])
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(ret))
+}
+
+/// 展开install_path模板，变量见PATH_VARS
+pub fn expand_install_path(template: &str, key: &str, channel: &str) -> Result<String> {
+    expand_install_path_env(template, key, channel, |name| env::var(name).ok())
+}
+
+/// 同expand_install_path，环境变量由env查询
+fn expand_install_path_env(template: &str, key: &str, channel: &str, env: impl Fn(&str) -> Option<String>) -> Result<String> {
+    expand_install_path_with(template, |var| resolve_path_var(var, key, channel, &env))
+}
+
+/// 检查模板里的变量是否都支持以及展开后的位置，不读取环境变量
+pub fn check_install_path(template: &str) -> Result<()> {
+    expand_install_path_with(template, |var| match PATH_VARS.contains(&var) {
+        true => Ok("x".to_string()),
+        false => resolve_path_var(var, "", "", &|_| None),
+    })
+    .map(|_| ())
+}
+
+/// install_path只能以目录变量开头或相对于程序目录  
+/// 其余部分展开后规范化，不能是绝对路径，也不能用`..`跳出基准目录
+fn expand_install_path_with(template: &str, resolve: impl Fn(&str) -> Result<String>) -> Result<String> {
+    let (base, rest) = match next_path_var(template, true)? {
+        Some((var, rest)) if BASE_VARS.contains(&var.as_str()) => (Some(resolve(&var)?), rest),
+        _ => (None, template),
+    };
+    let rest = expand_path_vars(rest, |var| match BASE_VARS.contains(&var) {
+        true => Err(anyhow!("{var}只能出现在install_path开头")),
+        false => resolve(var),
+    })?;
+    let relative = match base {
+        Some(_) => rest.trim_start_matches(['/', '\\']),
+        None => rest.as_str(),
+    };
+    let is_base = !relative.starts_with(['/', '\\'])
+        && relative.split(['/', '\\']).all(|c| c.is_empty() || c == ".");
+    let relative = match is_base {
+        true => None,
+        false => Some(normalize_relative_path(relative)
+            .map_err(|_| anyhow!("install_path必须以目录变量开头或是相对路径: {template}"))?),
+    };
+    Ok(match (base, relative) {
+        (Some(base), Some(relative)) => format!("{base}/{relative}"),
+        (Some(base), None) => base,
+        (None, Some(relative)) => relative,
+        (None, None) => ".".to_string(),
+    })
+}
+
+fn expand_path_vars(template: &str, resolve: impl Fn(&str) -> Result<String>) -> Result<String> {
+    let mut ret = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        match next_path_var(rest, rest.len() == template.len())? {
+            Some((var, after)) => {
+                ret.push_str(&resolve(&var)?);
+                rest = after;
+            }
+            None => {
+                ret.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// 支持的Hash算法
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgo {
+    Sha1,
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgo {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgo::Sha1 => "sha1",
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Blake3 => "blake3",
+        }
+    }
+
+    /// 十六进制摘要的长度
+    pub fn hex_len(&self) -> usize {
+        match self {
+            HashAlgo::Sha1 => 40,
+            HashAlgo::Sha256 | HashAlgo::Blake3 => 64,
+        }
+    }
+
+    pub fn digest(&self, contents: &[u8]) -> String {
+        match self {
+            HashAlgo::Sha1 => to_hex(&Sha1::digest(contents)),
+            HashAlgo::Sha256 => to_hex(&Sha256::digest(contents)),
+            HashAlgo::Blake3 => blake3::hash(contents).to_hex().to_string(),
+        }
+    }
+}
+
+impl FromStr for HashAlgo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha1" => Ok(HashAlgo::Sha1),
+            "sha256" => Ok(HashAlgo::Sha256),
+            "blake3" => Ok(HashAlgo::Blake3),
+            _ => Err(anyhow!("不支持的Hash算法: {s}")),
+        }
+    }
+}
+
+/// 带算法标记的Hash，格式为`算法:十六进制摘要`，如`sha256:…`  
+/// 不带前缀的旧格式视为sha1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileHash {
+    pub algo: HashAlgo,
+    pub hex: String,
+}
+
+impl FromStr for FileHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (algo, hex) = match s.split_once(':') {
+            Some((algo, hex)) => (algo.parse()?, hex),
+            None => (HashAlgo::Sha1, s),
+        };
+        if hex.len() != algo.hex_len() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow!("{} Hash格式错误: {s}", algo.name()));
+        }
+        Ok(FileHash { algo, hex: hex.to_ascii_lowercase() })
+    }
+}
+
+impl fmt::Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algo.name(), self.hex)
+    }
+}
+
+pub fn get_file_hash(filename: &str, algo: HashAlgo) -> Result<FileHash> {
+    let contents = std::fs::read(filename)?;
+    let hex = algo.digest(&contents);
+    info!("File: {filename}, {}: {hex}", algo.name());
+    Ok(FileHash { algo, hex })
+}
+
+/// 按期望Hash的算法计算文件摘要并比较
+pub fn check_file_hash(filename: &str, expected: &FileHash) -> Result<bool> {
+    Ok(get_file_hash(filename, expected.algo)? == *expected)
+}
+
+/// 解析十六进制文本形式的ed25519公钥
+pub fn parse_public_key(text: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = from_hex(text.trim())?
+        .try_into()
+        .map_err(|_| anyhow!("公钥长度错误"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// 校验分离签名，签名可以是64字节原始数据或十六进制文本
+pub fn verify_signature(content: &[u8], signature: &[u8], key: &VerifyingKey) -> Result<()> {
+    let bytes = if signature.len() == Signature::BYTE_SIZE {
+        signature.to_vec()
+    } else {
+        from_hex(std::str::from_utf8(signature)?.trim())?
+    };
+    let signature = Signature::from_slice(&bytes).map_err(|_| anyhow!("签名格式错误"))?;
+    key.verify_strict(content, &signature)
+        .map_err(|_| anyhow!("签名校验失败"))
+}
+
+/// 应用增量补丁，返回新文件内容  
+/// zstd格式的补丁以旧文件作为字典压缩新文件，即`zstd --patch-from=旧文件`
+pub fn apply_patch(format: &str, base: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    match format {
+        "zstd" => {
+            let mut decoder = zstd::stream::read::Decoder::with_dictionary(patch, base)?;
+            // patch-from生成的补丁窗口可能很大
+            decoder.window_log_max(31)?;
+            let mut ret = vec![];
+            std::io::copy(&mut decoder, &mut ret)?;
+            Ok(ret)
+        }
+        _ => Err(anyhow!("不支持的补丁格式: {format}")),
+    }
+}
+
+/// 便于阅读的大小，如`1.5 MB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// 剩余时间，如`5秒`、`2分05秒`、`1小时03分`
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    match secs {
+        0..60 => format!("{secs}秒"),
+        60..3600 => format!("{}分{:02}秒", secs / 60, secs % 60),
+        _ => format!("{}小时{:02}分", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_check_link() {
+    assert!(check_link("https://example.com/a?b=1&c=2").is_ok());
+    assert!(check_link("http://example.com/").is_ok());
+    assert!(check_link("file:///C:/Windows/System32/calc.exe").is_err());
+    assert!(check_link("ms-settings:").is_err());
+    assert!(check_link("calc & whoami").is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_format_size() {
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1536), "1.5 KB");
+    assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    assert_eq!(format_size(3 << 40), "3.0 TB");
+    assert_eq!(format_eta(Duration::from_secs(5)), "5秒");
+    assert_eq!(format_eta(Duration::from_secs(125)), "2分05秒");
+    assert_eq!(format_eta(Duration::from_secs(3780)), "1小时03分");
+}
+
+#[cfg(test)]
+#[test]
+fn test_file_hash() -> Result<()> {
+    // 旧格式视为sha1
+    let h: FileHash = "DA39A3EE5E6B4B0D3255BFEF95601890AFD80709".parse()?;
+    assert_eq!(h.algo, HashAlgo::Sha1);
+    assert_eq!(h.to_string(), "sha1:da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    // 空内容的摘要
+    for (algo, hex) in [
+        (HashAlgo::Sha256, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+        (HashAlgo::Blake3, "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+    ] {
+        assert_eq!(algo.digest(b""), hex);
+        let h: FileHash = format!("{}:{hex}", algo.name()).parse()?;
+        assert_eq!(h.algo, algo);
+    }
+    assert!("md5:d41d8cd98f00b204e9800998ecf8427e".parse::<FileHash>().is_err());
+    assert!("sha256:da39a3ee5e6b4b0d3255bfef95601890afd80709".parse::<FileHash>().is_err());
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_apply_patch() -> Result<()> {
+    use std::io::Write;
+
+    let base = b"{\"cards\": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]}".repeat(100);
+    let mut new = base.clone();
+    new.extend_from_slice(b"{\"cards\": [11]}");
+    let mut encoder = zstd::stream::write::Encoder::with_dictionary(vec![], 19, &base)?;
+    encoder.write_all(&new)?;
+    let patch = encoder.finish()?;
+    println!("patch: {} bytes", patch.len());
+    assert!(patch.len() < new.len() / 10);
+    assert_eq!(apply_patch("zstd", &base, &patch)?, new);
+    assert!(apply_patch("bsdiff", &base, &patch).is_err());
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_expand_install_path() -> Result<()> {
+    use std::collections::HashMap;
+
+    let vars = HashMap::from([
+        ("USERPROFILE", "/home/uma"),
+        ("XDG_DATA_HOME", "/home/uma/.data"),
+    ]);
+    let env = |name: &str| vars.get(name).map(|v| v.to_string());
+    let expand = |s: &str| expand_install_path_env(s, "ai_data", "beta", env);
+    assert_eq!(expand("%LocalAppData%/UmaAI/{key}")?, "/home/uma/.data/UmaAI/ai_data");
+    assert_eq!(expand("$XDG_DATA_HOME/UmaAI")?, "/home/uma/.data/UmaAI");
+    // 没有APPDATA时用Linux的配置目录
+    assert_eq!(expand("%appdata%/UmaAI")?, "/home/uma/.config/UmaAI");
+    assert_eq!(expand("~/UmaAI/{channel}")?, "/home/uma/UmaAI/beta");
+    assert_eq!(expand("db/a~b")?, "db/a~b");
+    assert_eq!(expand(".")?, ".");
+
+    let err = expand("%programfiles%/UmaAI").unwrap_err();
+    println!("{err}");
+    assert!(err.to_string().contains("%programfiles%"));
+    assert!(expand("$XDG_CACHE_HOME/UmaAI").is_err());
+    assert!(expand("{version}/UmaAI").is_err());
+    assert!(expand("%appdata/UmaAI").unwrap_err().to_string().contains("没有闭合"));
+    assert!(expand_install_path_env("{key}", "", "stable", env).is_err());
+    check_install_path("%appdata%/{key}/{channel}")?;
+    assert!(check_install_path("%temp%/UmaAI").is_err());
+
+    // 绝对路径和跳出基准目录的路径
+    for evil in ["C:/Windows/System32", "/etc", "\\\\server\\share", "%appdata%/../x", "UmaAI/%appdata%", "~/a/../../b"] {
+        assert!(check_install_path(evil).is_err(), "{evil}");
+        assert!(expand(evil).is_err(), "{evil}");
+    }
+    assert!(expand_install_path_env("{key}/data", "../x", "stable", env).is_err());
+    assert_eq!(expand("%appdata%")?, "/home/uma/.config");
+    assert_eq!(expand("./UmaAI//{key}/")?, "UmaAI/ai_data");
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_confine_path() -> Result<()> {
+    assert_eq!(normalize_relative_path("./db//cardDB.json")?, "db/cardDB.json");
+    assert_eq!(normalize_relative_path("db\\.\\umaDB.json")?, "db/umaDB.json");
+    for path in ["", ".", "../x.exe", "db/../../x.exe", "db\\..\\..\\x.exe", "/etc/passwd", "\\x", "C:/x.exe", "c:x.exe"] {
+        assert!(normalize_relative_path(path).is_err(), "{path}");
+    }
+    assert_eq!(confine_path("root", "./a/b")?, Path::new("root").join("a/b"));
+    assert!(confine_path("root", "a/../../b").is_err());
+    assert_eq!(tempfile_path("./db/a.json")?, "db/a.json.autoupdate");
+    assert!(tempfile_path("../a.json").is_err());
+    Ok(())
+}
@@ -0,0 +1,270 @@
+//! validate  
+//! 清单格式校验，格式错误的组件单独记录，不影响其他组件
+use crate::installer::*;
+use crate::utils::*;
+use crate::version::{parse_date, parse_semver};
+use crate::version_toml::*;
+use anyhow::{anyhow, Result};
+use log::warn;
+use semver::VersionReq;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 格式错误的组件，尽量保留名字和显示顺序
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct InvalidComponent {
+    pub name: String,
+    pub index: u32,
+    pub error: String,
+}
+
+/// 校验后的清单
+#[derive(Clone, Debug, Default)]
+pub struct ValidatedToml {
+    pub components: VersionToml,
+    pub invalid: HashMap<String, InvalidComponent>,
+    /// 顶层的min_updater_version
+    pub min_updater_version: Option<String>,
+}
+
+impl VersionInfo {
+    /// 检查必填项、日期、Hash和路径格式，返回所有问题
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = vec![];
+        if self.name.trim().is_empty() {
+            errors.push("name为空".to_string());
+        }
+        if parse_date(&self.date).is_none() {
+            errors.push(format!("date格式错误: {}", self.date));
+        }
+        if self.filelist.is_empty() {
+            errors.push("filelist为空".to_string());
+        }
+        for filename in &self.filelist {
+            if let Err(e) = check_relative_path(filename) {
+                errors.push(e.to_string());
+            }
+        }
+        if let Some(package) = &self.package {
+            if let Err(e) = check_relative_path(package) {
+                errors.push(e.to_string());
+            }
+        }
+        if let Some(notes_url) = &self.notes_url {
+            // 只允许https或相对于服务器的路径，内容由notes_hash校验
+            if notes_url.starts_with("http://") {
+                errors.push(format!("notes_url必须使用https: {notes_url}"));
+            } else if !is_absolute_url(notes_url) {
+                if let Err(e) = check_relative_path(notes_url) {
+                    errors.push(format!("notes_url{e}"));
+                }
+            }
+            match self.notes_hash.as_deref().map(|h| h.parse::<FileHash>()) {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => errors.push(format!("notes_hash{e}")),
+                None => errors.push("notes_url缺少notes_hash".to_string()),
+            }
+        }
+        if let Some(install_path) = &self.install_path {
+            if let Err(e) = check_install_path(install_path) {
+                errors.push(e.to_string());
+            }
+        }
+        let hashes = self.sha1.iter()
+            .chain(self.hashes.values())
+            .chain(self.package_sha1.iter())
+            .chain(self.package_hash.iter());
+        for hash in hashes {
+            if let Err(e) = hash.parse::<FileHash>() {
+                errors.push(e.to_string());
+            }
+        }
+        for filename in self.hashes.keys() {
+            if !self.filelist.contains(filename) {
+                errors.push(format!("hashes中的{filename}不在filelist里"));
+            }
+        }
+        for filename in self.sizes.keys() {
+            if !self.filelist.contains(filename) {
+                errors.push(format!("sizes中的{filename}不在filelist里"));
+            }
+        }
+        for (filename, patches) in &self.patches {
+            if !self.filelist.contains(filename) {
+                errors.push(format!("patches中的{filename}不在filelist里"));
+            }
+            for (base, patch) in patches {
+                if let Err(e) = base.parse::<FileHash>() {
+                    errors.push(e.to_string());
+                }
+                if let Err(e) = check_relative_path(&patch.patch) {
+                    errors.push(e.to_string());
+                }
+            }
+        }
+        if self.package.is_some() && self.kind() != ComponentKind::Package {
+            errors.push(format!("kind为{:?}的组件不能使用package", self.kind()));
+        }
+        for req in self.requires.values() {
+            if let Err(e) = VersionReq::parse(req) {
+                errors.push(format!("requires条件错误 {req}: {e}"));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(errors.join("; ")))
+        }
+    }
+}
+
+/// 逐个组件解析并校验清单  
+/// 只有整个文件不是合法TOML时才返回错误
+/// 把一个条目解析为VersionInfo，只检查字段类型，不做validate
+pub fn parse_component(key: &str, value: toml::Value) -> Result<VersionInfo> {
+    let mut info: VersionInfo = value.try_into().map_err(|e: toml::de::Error| anyhow!("{}", e.message()))?;
+    info.key = key.to_string();
+    // 兼容没有kind的旧版清单
+    if info.kind.is_none() && key == LEGACY_UPDATER_KEY {
+        info.kind = Some(ComponentKind::SelfUpdate);
+    }
+    Ok(info)
+}
+
+pub fn parse_version_toml(content: &str) -> Result<ValidatedToml> {
+    let table: toml::Table = toml::from_str(content)?;
+    let mut ret = ValidatedToml::default();
+    for (key, value) in table {
+        if key == "min_updater_version" {
+            match value.as_str().filter(|v| parse_semver(v).is_some()) {
+                Some(v) => ret.min_updater_version = Some(v.to_string()),
+                None => warn!("min_updater_version格式错误，已忽略: {value}"),
+            }
+            continue;
+        }
+        // 解析失败时也尽量取出名字和顺序用于显示
+        let mut invalid = InvalidComponent {
+            name: value.get("name").and_then(|v| v.as_str()).unwrap_or(&key).to_string(),
+            index: value.get("index").and_then(|v| v.as_integer()).unwrap_or(0) as u32,
+            error: String::new(),
+        };
+        let checked = parse_component(&key, value).and_then(|info| info.validate().map(|_| info));
+        match checked {
+            Ok(info) => {
+                ret.components.insert(key, info);
+            }
+            Err(e) => {
+                warn!("组件 {key} 格式错误: {e}");
+                invalid.error = e.to_string();
+                ret.invalid.insert(key, invalid);
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_version_toml() -> Result<()> {
+    let content = r#"
+min_updater_version = "0.1.3"
+
+[ai_data]
+name = "AI数据"
+date = "2025-02-01 13:54:57"
+filelist = ["db/cardDB.json"]
+index = 2
+notes = "- 新增**凯旋门**数据"
+notes_url = "https://example.com/notes.md"
+notes_hash = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+
+[cn_data]
+name = "国服数据"
+date = "2025-02-01 13:54:57.123456"
+filelist = ["db/cnDB.json"]
+index = 7
+
+[auto_update]
+name = "自动更新工具"
+date = "2025-02-11"
+filelist = []
+index = 6
+sha1 = "not a hash"
+
+[ura_data]
+name = "小黑板数据"
+date = "2025-02-11 13:54:57"
+index = 3
+
+[tools]
+name = "工具"
+date = "2025-02-11 13:54:57"
+filelist = ["tools.exe"]
+index = 5
+kind = "app"
+package = "tools.zip"
+sizes = { "tools.exe" = 1024, "other.exe" = 2048 }
+
+[evil]
+name = "evil"
+date = "2025-02-11 13:54:57"
+filelist = ["../../x.exe", "/etc/passwd"]
+notes_url = "../notes.md"
+index = 4
+"#;
+    let ret = parse_version_toml(content)?;
+    assert_eq!(ret.components.len(), 2);
+    assert!(ret.components.contains_key("ai_data"));
+    // 秒可以带小数
+    assert!(ret.components.contains_key("cn_data"));
+    assert_eq!(ret.components["ai_data"].kind(), ComponentKind::Data);
+    assert_eq!(ret.min_updater_version.as_deref(), Some("0.1.3"));
+
+    let err = &ret.invalid["auto_update"];
+    println!("{err:?}");
+    assert_eq!(err.name, "自动更新工具");
+    assert_eq!(err.index, 6);
+    assert!(err.error.contains("date"));
+    assert!(err.error.contains("filelist"));
+    assert!(err.error.contains("Hash"));
+    assert!(ret.invalid["ura_data"].error.contains("filelist"));
+    assert!(ret.invalid["evil"].error.contains("../../x.exe"));
+    assert!(ret.invalid["evil"].error.contains("/etc/passwd"));
+    assert!(ret.invalid["evil"].error.contains("notes_url"));
+
+    // 更新说明必须是https或相对路径，并且有Hash
+    let notes = parse_version_toml(r#"
+[plain]
+name = "明文"
+date = "2025-02-11 13:54:57"
+filelist = ["a.json"]
+index = 1
+notes_url = "http://example.com/notes.md"
+notes_hash = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+
+[unsigned]
+name = "无Hash"
+date = "2025-02-11 13:54:57"
+filelist = ["a.json"]
+index = 2
+notes_url = "notes.md"
+"#)?;
+    assert!(notes.invalid["plain"].error.contains("https"));
+    assert!(notes.invalid["unsigned"].error.contains("notes_hash"));
+
+    assert!(ret.invalid["tools"].error.contains("package"));
+    assert!(ret.invalid["tools"].error.contains("other.exe"));
+
+    // 旧版清单的auto_update视为更新工具自身，和显示名字无关
+    let legacy = parse_version_toml(r#"
+[auto_update]
+name = "改名后的更新工具"
+date = "2025-02-11 13:54:57"
+filelist = ["uma-autoupdate.exe"]
+index = 6
+"#)?;
+    assert_eq!(legacy.components["auto_update"].kind(), ComponentKind::SelfUpdate);
+
+    assert!(parse_version_toml("[broken").is_err());
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! version  
+//! 组件版本模型，ver能按semver解析时优先使用，否则按date比较
+use chrono::NaiveDateTime;
+use semver::{Version, VersionReq};
+use std::cmp::Ordering;
+use std::fmt;
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// 解析清单中的日期，秒后面可以带小数
+pub fn parse_date(s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").ok()
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ComponentVersion {
+    pub semver: Option<Version>,
+    pub date: Option<NaiveDateTime>,
+}
+
+/// 远程版本相对本地版本的状态
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateState {
+    /// 远程版本更新
+    Newer,
+    /// 版本相同
+    Same,
+    /// 远程版本更旧，更新会降级
+    Older,
+    /// 缺少版本信息，无法比较
+    Unknown,
+}
+
+impl From<Ordering> for UpdateState {
+    fn from(ord: Ordering) -> Self {
+        match ord {
+            Ordering::Greater => UpdateState::Newer,
+            Ordering::Equal => UpdateState::Same,
+            Ordering::Less => UpdateState::Older,
+        }
+    }
+}
+
+/// 宽松地解析semver，允许`v`前缀和省略patch，如`v0.2`
+pub fn parse_semver(s: &str) -> Option<Version> {
+    let s = s.trim();
+    let s = s.strip_prefix(['v', 'V']).unwrap_or(s);
+    Version::parse(s)
+        .or_else(|_| Version::parse(&format!("{s}.0")))
+        .ok()
+}
+
+impl ComponentVersion {
+    pub fn new(ver: Option<&str>, date: &str) -> Self {
+        ComponentVersion {
+            semver: ver.and_then(parse_semver),
+            date: parse_date(date),
+        }
+    }
+
+    /// 是否满足依赖条件，没有semver时只满足`*`
+    pub fn satisfies(&self, req: &VersionReq) -> bool {
+        match &self.semver {
+            Some(v) => req.matches(v),
+            None => *req == VersionReq::STAR,
+        }
+    }
+
+    /// 比较self(远程)和local(本地)  
+    /// 双方都有semver时按semver比较，否则按日期比较
+    pub fn compare(&self, local: &ComponentVersion) -> UpdateState {
+        match (&self.semver, &local.semver, &self.date, &local.date) {
+            (Some(v1), Some(v2), _, _) => v1.cmp(v2).into(),
+            (_, _, Some(d1), Some(d2)) => d1.cmp(d2).into(),
+            _ => UpdateState::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ComponentVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.semver, &self.date) {
+            (Some(v), _) => write!(f, "{v}"),
+            (None, Some(d)) => write!(f, "{}", d.format("%Y-%m-%d")),
+            (None, None) => write!(f, "未知"),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_compare_version() {
+    let v = |ver: Option<&str>, date: &str| ComponentVersion::new(ver, date);
+    let old = v(Some("0.1.2"), "2025-02-11 13:54:57");
+    let new = v(Some("v0.1.10"), "2025-02-01 00:00:00");
+    // semver优先于日期
+    assert_eq!(new.compare(&old), UpdateState::Newer);
+    assert_eq!(old.compare(&new), UpdateState::Older);
+    assert_eq!(old.compare(&old), UpdateState::Same);
+    // 缺少semver时按日期
+    let d1 = v(None, "2025-02-01 13:54:57");
+    let d2 = v(Some("0.2"), "2025-02-11 13:54:57");
+    assert_eq!(d2.compare(&d1), UpdateState::Newer);
+    assert_eq!(v(None, "bad date").compare(&d1), UpdateState::Unknown);
+    let frac = v(None, "2025-02-01 13:54:57.250");
+    assert_eq!(frac.compare(&d1), UpdateState::Newer);
+    assert_eq!(new.to_string(), "0.1.10");
+    assert_eq!(d1.to_string(), "2025-02-01");
+}
@@ -1,14 +1,61 @@
 use crate::utils::*;
+use crate::Res;
 use anyhow::{anyhow, Result};
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::Local;
+use ed25519_dalek::VerifyingKey;
 use log::{info, warn};
-use reqwest::{header::REFERER, Client};
+use crate::download::{DownloadFile, DownloadPatch};
+use crate::installer::*;
+use crate::local_conf::*;
+use crate::remote_cache::*;
+use crate::settings::*;
+use crate::validate::*;
+use crate::version::*;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::default::Default;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-use std::{env, fs, process};
+use std::fs;
+
+fn default_patch_format() -> String {
+    "zstd".to_string()
+}
+
+/// 增量补丁
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PatchInfo {
+    /// 补丁文件名，和组件的文件放在同一目录
+    pub patch: String,
+    /// 补丁格式，目前支持zstd
+    #[serde(default = "default_patch_format")]
+    pub format: String,
+}
+
+/// 安装回执，记录安装时实际写入磁盘的文件
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct FileReceipt {
+    /// 实际写入的路径
+    pub path: String,
+    /// 文件大小
+    pub size: u64,
+    /// 文件Hash，带算法标记
+    pub hash: String,
+    /// 安装时间
+    pub installed: String,
+}
+
+impl FileReceipt {
+    /// 计算path的大小和Hash
+    pub fn new(path: &str, algo: HashAlgo) -> Result<Self> {
+        Ok(FileReceipt {
+            path: path.to_string(),
+            size: fs::metadata(path)?.len(),
+            hash: get_file_hash(path, algo)?.to_string(),
+            installed: Local::now().format(DATE_FORMAT).to_string(),
+        })
+    }
+}
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct VersionInfo {
@@ -22,94 +69,395 @@ pub struct VersionInfo {
     pub filelist: Vec<String>,
     /// 文件列表中第一个文件的Hash，实际不可空
     pub sha1: Option<String>,
+    /// 每个文件的Hash，文件名 -> `算法:摘要`，如`sha256:…`、`blake3:…`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hashes: HashMap<String, String>,
+    /// 每个文件的字节数，文件名 -> 大小，用于发现被截断或错误的下载
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub sizes: HashMap<String, u64>,
+    /// 增量补丁，文件名 -> 旧文件Hash -> 补丁
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub patches: HashMap<String, HashMap<String, PatchInfo>>,
     /// 完整压缩包名，可选
     pub package: Option<String>,
     /// 完整压缩包Hash
     pub package_sha1: Option<String>,
+    /// 完整压缩包Hash，带算法标记，优先于package_sha1
+    pub package_hash: Option<String>,
+    /// 完整压缩包的字节数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_size: Option<u64>,
     /// App版本说明，可选
     pub ver: Option<String>,
     /// 下载目录，可选
     pub install_path: Option<String>,
+    /// 强制更新，需要更新时不用点击
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mandatory: bool,
+    /// 已弃用，不再更新，界面上提供卸载
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deprecated: bool,
+    /// 本地记录安装前的备份目录，用于回滚
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+    /// 本地记录安装时所在的频道
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+    /// 前置组件，组件key -> semver条件，如`{ auto_update = ">=0.1.3" }`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub requires: HashMap<String, String>,
+    /// 更新说明，Markdown格式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// 更新说明的地址，相对路径时和组件的文件放在同一目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes_url: Option<String>,
+    /// notes_url内容的Hash，使清单签名也覆盖下载的更新说明
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes_hash: Option<String>,
+    /// 组件key，解析清单时填入，用于install_path中的{key}
+    #[serde(skip)]
+    pub key: String,
+    /// 组件类型，决定安装方式，旧版清单没有时按package推断
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ComponentKind>,
+    /// 本地记录每个文件的安装回执，文件名 -> 回执
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub receipts: HashMap<String, FileReceipt>,
 }
 
 impl VersionInfo {
-    pub fn newer_than(&self, other: &VersionInfo) -> bool {
-        let date1 = NaiveDateTime::parse_from_str(&self.date, "%Y-%m-%d %H:%M:%S");
-        let date2 = NaiveDateTime::parse_from_str(&other.date, "%Y-%m-%d %H:%M:%S");
-        match (date1, date2) {
-            (Ok(d1), Ok(d2)) => d1 > d2,
-            (Ok(_), Err(_)) => true,
-            _ => false,
-        }
-    }
-
-    /// 检查filelist的第一个文件的sha1是否为记录的值
-    pub fn verify(&self) -> bool {
-        if let Some(sha1) = self.sha1.as_deref() {
-            let filename = format!("{}.autoupdate", self.filelist[0]);
-            match get_file_sha1(&filename) {
-                Ok(digest) => {
-                    if digest != sha1 {
-                        warn!("{filename} SHA1错误");
-                    }
+    pub fn version(&self) -> ComponentVersion {
+        ComponentVersion::new(self.ver.as_deref(), &self.date)
+    }
+
+    /// 组件类型，没有写明时有package的视为压缩包，否则为数据文件
+    pub fn kind(&self) -> ComponentKind {
+        self.kind.unwrap_or(if self.package.is_some() {
+            ComponentKind::Package
+        } else {
+            ComponentKind::Data
+        })
+    }
+
+    /// 比较self(远程)和other(本地)的版本
+    pub fn compare(&self, other: &VersionInfo) -> UpdateState {
+        self.version().compare(&other.version())
+    }
+
+    /// 返回文件的期望Hash  
+    /// 优先使用hashes里的记录，旧版清单只有filelist[0]对应的sha1
+    pub fn expected_hash(&self, filename: &str) -> Result<Option<FileHash>> {
+        self.hashes
+            .get(filename)
+            .map(|s| s.as_str())
+            .or_else(|| {
+                self.sha1
+                    .as_deref()
+                    .filter(|_| self.filelist.first().is_some_and(|f| f == filename))
+            })
+            .map(|s| s.parse())
+            .transpose()
+    }
+
+    /// 检查filelist中每个下载文件的Hash  
+    /// 任何一个文件校验失败都会删除本次下载的临时文件并返回出错的文件名
+    pub fn verify(&self) -> Result<()> {
+        let ret = self.verify_files();
+        if ret.is_err() {
+            self.remove_tempfiles();
+        }
+        ret
+    }
+
+    fn verify_files(&self) -> Result<()> {
+        for filename in &self.filelist {
+            let tempfile = tempfile_path(filename)?;
+            if !fs::exists(&tempfile)? {
+                return Err(anyhow!("{filename} 未下载"));
+            }
+            let expected = self
+                .expected_hash(filename)?
+                .ok_or_else(|| anyhow!("{filename} 没有记录Hash"))?;
+            let ok = check_file_hash(&tempfile, &expected)
+                .map_err(|e| anyhow!("计算{filename} {}时出错: {e}", expected.algo.name()))?;
+            if !ok {
+                return Err(anyhow!("{filename} {}错误", expected.algo.name()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除本组件下载的临时文件
+    pub fn remove_tempfiles(&self) {
+        for filename in &self.filelist {
+            let Ok(tempfile) = tempfile_path(filename) else {
+                continue;
+            };
+            if fs::exists(&tempfile).unwrap_or(false) {
+                info!("删除 {tempfile}");
+                if let Err(e) = fs::remove_file(&tempfile) {
+                    warn!("删除{tempfile}失败: {e}");
                 }
-                Err(e) => {
-                    warn!("计算{filename} SHA1时出错: {e:?}");
+            }
+        }
+    }
+
+    /// 完整压缩包的期望Hash，package_hash优先
+    pub fn expected_package_hash(&self) -> Result<Option<FileHash>> {
+        self.package_hash
+            .as_deref()
+            .or(self.package_sha1.as_deref())
+            .map(|s| s.parse())
+            .transpose()
+    }
+
+    /// 检查下载的完整压缩包，失败时删除临时文件
+    pub fn verify_package(&self) -> Result<()> {
+        let package = self.package.as_deref().ok_or(anyhow!("没有完整压缩包"))?;
+        let tempfile = tempfile_path(package)?;
+        let ret = match self.expected_package_hash()? {
+            Some(expected) if fs::exists(&tempfile)? => {
+                match check_file_hash(&tempfile, &expected) {
+                    Ok(true) => Ok(()),
+                    Ok(false) => Err(anyhow!("{package} {}错误", expected.algo.name())),
+                    Err(e) => Err(anyhow!("计算{package} {}时出错: {e}", expected.algo.name())),
                 }
-            } 
+            }
+            Some(_) => Err(anyhow!("{package} 未下载")),
+            None => Err(anyhow!("{package} 没有记录Hash")),
+        };
+        if ret.is_err() && fs::exists(&tempfile).unwrap_or(false) {
+            let _ = fs::remove_file(&tempfile);
         }
-        true
+        ret
     }
 
+    /// 把完整压缩包解压到安装目录，每写入一个文件就记录一条回执  
+    /// 包含绝对路径或`..`的条目会导致整个压缩包被拒绝
+    pub fn install_package(&self, receipts: &mut HashMap<String, FileReceipt>) -> Result<()> {
+        let package = self.package.as_deref().ok_or(anyhow!("没有完整压缩包"))?;
+        let tempfile = tempfile_path(package)?;
+        let install_path = self.get_install_dir()?;
+        let mut archive = zip::ZipArchive::new(fs::File::open(&tempfile)?)?;
+        // 先检查所有条目，避免解压到一半才发现问题
+        let mut entries = vec![];
+        for i in 0..archive.len() {
+            let entry = archive.by_index(i)?;
+            let filename = entry
+                .enclosed_name()
+                .and_then(|p| normalize_relative_path(&p.to_string_lossy()).ok())
+                .ok_or(anyhow!("{package} 包含非法路径: {}", entry.name()))?;
+            entries.push((i, filename, entry.is_dir()));
+        }
+        fs::create_dir_all(&install_path)?;
+        for (i, filename, is_dir) in entries {
+            let target = confine_path(&install_path, &filename)?;
+            create_parent_inside(&install_path, &target)?;
+            if is_dir {
+                fs::create_dir_all(&target)?;
+                continue;
+            }
+            info!("Extract {package} -> {target:?}");
+            let mut entry = archive.by_index(i)?;
+            let mut file = fs::File::create(&target)?;
+            std::io::copy(&mut entry, &mut file)?;
+            drop(file);
+            let receipt = FileReceipt::new(&target.to_string_lossy(), self.receipt_algo(&filename))?;
+            receipts.insert(filename, receipt);
+        }
+        fs::remove_file(&tempfile)?;
+        Ok(())
+    }
+
+    /// 展开install_path模板，没有时为当前目录
     pub fn get_install_dir(&self) -> Result<String> {
-        let mut install_path = self.install_path.as_deref().unwrap_or(".").to_string();
-        if install_path.contains("%localappdata%") {
-            let data_path = env::var("LOCALAPPDATA")?;
-            info!("local-app-data: {data_path}");
-            install_path = install_path.replace("%localappdata%", &data_path);
+        let template = self.install_path.as_deref().unwrap_or(".");
+        let channel = self.channel.unwrap_or_default();
+        let install_path = expand_install_path(template, &self.key, channel.name())
+            .map_err(|e| anyhow!("{} {e}", self.name))?;
+        if install_path != template {
+            info!("install_path: {template} -> {install_path}");
         }
         Ok(install_path)
     }
 
-    pub fn get_local_sha1(&self) -> Result<Option<String>> {
+    /// 文件在本地的实际路径
+    pub fn local_file(&self, filename: &str) -> Result<String> {
         let install_dir = self.get_install_dir()?;
-        // 拿name判断一下, emm...
-        let local_name = if self.name == "自动更新工具" {
-            get_exe_name()?.replace(".\\", "")
-        } else {
-            self.filelist[0].clone()
-        };
-        let filename = format!("{}/{}", install_dir, &local_name);
+        let is_self = self.kind() == ComponentKind::SelfUpdate
+            && self.filelist.first().is_some_and(|f| f == filename);
+        if is_self {
+            let exe_name = get_exe_name()?.replace(".\\", "");
+            return Ok(format!("{install_dir}/{exe_name}"));
+        }
+        // 清单里的路径不能离开安装目录
+        Ok(confine_path(&install_dir, filename)?.to_string_lossy().to_string())
+    }
+
+    /// 本地文件和某个补丁的旧文件Hash一致时，返回该补丁  
+    /// 新文件没有记录Hash时无法校验补丁结果，不使用补丁
+    pub fn find_patch(&self, filename: &str) -> Option<DownloadPatch> {
+        let patches = self.patches.get(filename)?;
+        let expected = self.expected_hash(filename).ok()??;
+        let local = self.local_file(filename).ok()?;
+        if !fs::exists(&local).unwrap_or(false) {
+            return None;
+        }
+        patches.iter().find_map(|(base, info)| {
+            let base: FileHash = base.parse().ok()?;
+            check_file_hash(&local, &base).ok()?.then(|| DownloadPatch {
+                patch: info.patch.clone(),
+                format: info.format.clone(),
+                base: local.clone(),
+                expected: expected.to_string(),
+            })
+        })
+    }
+
+    /// 逐个下载filelist时的下载列表，能用增量补丁的文件带上补丁
+    pub fn download_files(&self, key: &str) -> Vec<DownloadFile> {
+        self.filelist
+            .iter()
+            .map(|f| DownloadFile {
+                patch: self.find_patch(f),
+                size: self.sizes.get(f).copied(),
+                ..DownloadFile::new(key, f)
+            })
+            .collect()
+    }
+
+    /// 计算本地filelist[0]的Hash，算法与清单记录的一致
+    pub fn get_local_hash(&self) -> Result<Option<FileHash>> {
+        let first = self.filelist.first().ok_or(anyhow!("{} 文件列表为空", self.name))?;
+        let filename = self.local_file(first)?;
         if fs::exists(&filename)? {
-            let digest = get_file_sha1(&filename)?;
+            let algo = self
+                .expected_hash(first)?
+                .map(|h| h.algo)
+                .unwrap_or(HashAlgo::Sha1);
+            let digest = get_file_hash(&filename, algo)?;
             Ok(Some(digest))
         } else {
-            info!("本地文件 {local_name} 不存在.");
+            info!("本地文件 {filename} 不存在.");
             Ok(None)
         }
     }
 
-    pub fn install(&self) -> Result<()> {
+    /// 回执使用的Hash算法，与清单记录的一致
+    fn receipt_algo(&self, filename: &str) -> HashAlgo {
+        self.expected_hash(filename)
+            .unwrap_or(None)
+            .map(|h| h.algo)
+            .unwrap_or(HashAlgo::Sha256)
+    }
+
+    /// 更新工具替换自身前，按下载的临时文件生成回执
+    pub fn self_receipts(&self) -> Result<HashMap<String, FileReceipt>> {
+        let first = self.filelist.first().ok_or(anyhow!("{} 文件列表为空", self.name))?;
+        let receipt = FileReceipt {
+            path: self.local_file(first)?,
+            ..FileReceipt::new(&tempfile_path(first)?, self.receipt_algo(first))?
+        };
+        Ok(HashMap::from([(first.clone(), receipt)]))
+    }
+
+    /// 对照local的安装回执检查self(远程)的文件是否都已正确安装  
+    /// 本地记录没有回执(旧版本写入)时返回None
+    pub fn check_receipts(&self, local: &VersionInfo) -> Option<bool> {
+        if local.receipts.is_empty() {
+            return None;
+        }
+        let ok = self.filelist.iter().all(|filename| {
+            let Some(receipt) = local.receipts.get(filename) else {
+                info!("{filename} 没有安装回执");
+                return false;
+            };
+            let hash_ok = match self.expected_hash(filename) {
+                Ok(Some(expected)) => receipt.hash.parse::<FileHash>().is_ok_and(|h| h == expected),
+                _ => true,
+            };
+            let path_ok = self.local_file(filename).is_ok_and(|p| p == receipt.path);
+            let size_ok = fs::metadata(&receipt.path).is_ok_and(|m| m.len() == receipt.size);
+            hash_ok && path_ok && size_ok
+        });
+        Some(ok)
+    }
+
+    /// 删除old里有、新版本filelist里已经没有的文件，返回删除的路径  
+    /// 调用前应先备份old
+    pub fn remove_orphans(&self, old: &VersionInfo) -> Result<Vec<String>> {
+        let current: Vec<_> = self
+            .filelist
+            .iter()
+            .filter_map(|f| self.local_file(f).ok())
+            .collect();
+        let orphans: Vec<_> = old
+            .filelist
+            .iter()
+            .filter(|f| !self.filelist.contains(f))
+            .map(|f| old.local_file(f))
+            .collect::<Result<_>>()?;
+        let mut removed = vec![];
+        // 改名前后映射到同一路径的文件(如更新工具自身)不删除
+        for path in orphans.into_iter().filter(|p| !current.contains(p)) {
+            if fs::exists(&path)? {
+                info!("删除已移除的文件 {path}");
+                fs::remove_file(&path)?;
+                removed.push(path);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// 删除本地记录对应的所有文件，返回删除的路径  
+    /// 调用前应先备份
+    pub fn uninstall(&self) -> Result<Vec<String>> {
+        if self.kind() == ComponentKind::SelfUpdate {
+            return Err(anyhow!("不能卸载更新工具自身"));
+        }
+        let paths = self
+            .filelist
+            .iter()
+            .map(|f| self.local_file(f))
+            .collect::<Result<Vec<_>>>()?;
+        let mut removed = vec![];
+        for path in paths {
+            if fs::exists(&path)? {
+                info!("卸载 {path}");
+                fs::remove_file(&path)?;
+                removed.push(path);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// 复制下载的文件到安装目录，每写入一个文件就记录一条回执  
+    /// 中途出错时receipts里只有已经写入的文件
+    pub fn install(&self, receipts: &mut HashMap<String, FileReceipt>) -> Result<()> {
         let install_path = self.get_install_dir()?;
         if !fs::exists(&install_path)? {
             info!("新建目录 {install_path}");
             fs::create_dir_all(&install_path)?;
         }
         for filename in &self.filelist {
-            let tempfile = format!("{filename}.autoupdate");
+            let tempfile = tempfile_path(filename)?;
             if fs::exists(&tempfile)? {
-                let new_file = PathBuf::from(&install_path).join(filename);
+                let new_file = confine_path(&install_path, filename)?;
+                create_parent_inside(&install_path, &new_file)?;
                 info!("Copy {tempfile} -> {new_file:?}");
                 // windows的限制，只能复制+删除，不能rename
                 fs::copy(&tempfile, &new_file)?;
                 fs::remove_file(&tempfile)?;
+                let receipt = FileReceipt::new(&self.local_file(filename)?, self.receipt_algo(filename))?;
+                receipts.insert(filename.clone(), receipt);
             }
         }
         Ok(())
     }
 }
 
-type VersionToml = HashMap<String, VersionInfo>;
+pub type VersionToml = HashMap<String, VersionInfo>;
 
 /// 提供给前端的的版本信息集合  
 /// (可能获取不到)可以为空
@@ -119,6 +467,17 @@ pub struct VersionData {
     pub local: Option<VersionToml>,
     /// 远程版本信息
     pub remote: Option<VersionToml>,
+    /// 远程清单里格式错误的组件
+    #[serde(default)]
+    pub invalid: HashMap<String, InvalidComponent>,
+    /// 远程清单要求的最低更新工具版本
+    pub min_updater_version: Option<String>,
+    /// 远程清单所属的频道
+    #[serde(default)]
+    pub channel: Channel,
+    /// 远程清单来自缓存时为缓存最后确认的时间
+    #[serde(default)]
+    pub stale_since: Option<String>,
 }
 
 impl VersionData {
@@ -127,66 +486,285 @@ impl VersionData {
         self.remote.as_ref().or(self.local.as_ref())
     }
 
-    pub fn update_and_save(&mut self, key: &str) -> Result<()> {
-        let mut local = self.local.clone().unwrap_or_default();
-        if let Some(remote) = self.remote.as_ref() {
-            if let Some(info) = remote.get(key) {
-                local.insert(key.to_string(), info.clone());
-            }
-            info!("update version.toml: {key}");
-            let mut file = fs::File::create("version.toml")?;
-            file.write_all(toml::to_string_pretty(&local)?.as_bytes())?;
-            self.local = Some(local);
+    /// 要显示的组件: 有效配置里的、格式错误的，以及远程清单里已经没有的本地组件
+    pub fn component_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .pick()
+            .into_iter()
+            .chain(self.local.as_ref())
+            .flat_map(|conf| conf.keys())
+            .chain(self.invalid.keys())
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// 安装后用远程版本和安装回执更新本地记录，backup为安装前的备份目录  
+    /// 安装失败时也要记录已写入的文件，缺少回执的组件会显示为需要更新
+    pub fn update_and_save(
+        &mut self,
+        key: &str,
+        backup: Option<String>,
+        receipts: HashMap<String, FileReceipt>,
+        installed: bool,
+    ) -> Result<()> {
+        if let Some(info) = self.installed_record(key, backup, receipts, installed) {
+            self.save_local(key, info)?;
         }
         Ok(())
     }
+
+    /// 安装后要写入的本地记录  
+    /// 安装失败时保留原来的版本，只更新已写入文件的回执；原来没有安装时不写入记录
+    fn installed_record(
+        &self,
+        key: &str,
+        backup: Option<String>,
+        receipts: HashMap<String, FileReceipt>,
+        installed: bool,
+    ) -> Option<VersionInfo> {
+        if !installed {
+            let mut info = self.local.as_ref()?.get(key)?.clone();
+            info.receipts.extend(receipts);
+            return Some(info);
+        }
+        let mut info = self.remote.as_ref()?.get(key)?.clone();
+        info.channel = Some(self.channel);
+        info.backup = backup;
+        info.receipts = receipts;
+        Some(info)
+    }
+
+    /// 写入一条本地记录并保存version.toml
+    pub fn save_local(&mut self, key: &str, info: VersionInfo) -> Result<()> {
+        self.local = Some(update_conf(LOCAL_CONF, key, info, self.local.clone())?);
+        Ok(())
+    }
+
+    /// 删除一条本地记录并保存version.toml
+    pub fn remove_local(&mut self, key: &str) -> Result<()> {
+        self.local = Some(remove_conf(LOCAL_CONF, key, self.local.clone())?);
+        Ok(())
+    }
 }
 
-/// 获取本地配置文件
-pub fn get_local_conf() -> Result<Option<VersionToml>> {
-    let path = "version.toml";
-    if Path::new(path).exists() {
-        let content = fs::read_to_string(path)?;
-        let ret: VersionToml = toml::from_str(&content)?;
-        Ok(Some(ret))
+/// 清单签名公钥，以及服务器没有发布签名时是否拒绝清单  
+/// 发布流程: 用发布私钥对频道目录下的version.toml生成ed25519分离签名，
+/// 以十六进制写入同目录的version.toml.sig，再上传到所有服务器。
+/// 默认拒绝没有签名的清单，settings.toml里设置`allow_unsigned_manifest = true`才放行
+#[derive(Clone, Debug)]
+pub struct ManifestKey {
+    pub key: VerifyingKey,
+    pub required: bool,
+}
+
+impl ManifestKey {
+    pub fn new(key: VerifyingKey, required: bool) -> Self {
+        ManifestKey { key, required }
+    }
+}
+
+/// 内嵌的清单签名公钥  
+/// res/manifest.pub由发布流程放入发布签名公钥(十六进制)，缺少时拒绝所有清单
+pub fn manifest_public_key() -> Result<VerifyingKey> {
+    let file = Res::get("manifest.pub").ok_or(anyhow!("缺少清单签名公钥res/manifest.pub，无法校验远程清单"))?;
+    parse_public_key(std::str::from_utf8(&file.data)?)
+}
+
+async fn fetch_bytes(cli: &Client, mirror: &Mirror, url: &str) -> Result<Vec<u8>> {
+    let resp = mirror.get(cli, url).send().await?;
+    if resp.status().is_success() {
+        Ok(resp.bytes().await?.to_vec())
     } else {
-        Ok(None)
+        Err(anyhow!("{url}: {}", resp.status()))
     }
 }
 
-/// 获取远程配置文件
-pub async fn get_remote_conf() -> Result<VersionToml> {
-    let base_url = "https://cdn2.viktorlab.cn/uma";
-    let url = format!("{base_url}/version.toml");
-    let referer = "https://viktorlab.cn";
+/// notes_url是否为完整的http地址
+pub fn is_absolute_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// 获取组件的更新说明，notes_url是相对路径时按顺序尝试每个服务器  
+/// 内容必须和清单里的notes_hash一致
+pub async fn fetch_notes(mirrors: &[Mirror], channel: Channel, key: &str, notes_url: &str, expected: &FileHash) -> Result<String> {
     let cli = Client::new();
-    let resp = cli.get(url).header(REFERER, referer).send().await?;
+    let check = |content: Vec<u8>| {
+        if expected.algo.digest(&content) != expected.hex {
+            return Err(anyhow!("更新说明{}错误", expected.algo.name()));
+        }
+        Ok(String::from_utf8(content)?)
+    };
+    if is_absolute_url(notes_url) {
+        let resp = cli.get(notes_url).send().await?.error_for_status()?;
+        return check(resp.bytes().await?.to_vec());
+    }
+    let file = DownloadFile { channel, ..DownloadFile::new(key, notes_url) };
+    let mut errors = vec![];
+    for mirror in mirrors {
+        match fetch_bytes(&cli, mirror, &file.url(mirror)).await.and_then(check) {
+            Ok(notes) => return Ok(notes),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    Err(anyhow!("获取更新说明失败: {}", errors.join("; ")))
+}
 
-    if resp.status().is_success() {
-        let content = String::from_utf8(resp.bytes().await?.to_vec())?;
-        let ret: VersionToml = toml::from_str(&content)?;
-        Ok(ret)
+/// 获取到的远程清单
+#[derive(Clone, Debug)]
+pub struct RemoteConf {
+    pub conf: ValidatedToml,
+    /// 提供清单的服务器
+    pub mirror: String,
+    /// 服务器都不可用时使用缓存，记录缓存最后确认的时间
+    pub stale_since: Option<String>,
+}
+
+/// 校验签名并解析清单，签名为空表示服务器没有发布签名
+fn parse_signed_conf(content: &[u8], signature: &[u8], channel: Channel, key: &ManifestKey) -> Result<ValidatedToml> {
+    if signature.is_empty() && !key.required {
+        warn!("远程清单没有签名，跳过校验");
     } else {
-        Err(anyhow!(resp.status().to_string()))
+        verify_signature(content, signature, &key.key)
+            .map_err(|e| anyhow!("远程清单{e}"))?;
+    }
+    let content = std::str::from_utf8(content)?;
+    let mut conf = parse_version_toml(content)?;
+    // 远程组件记录来源频道，用于install_path中的{channel}
+    for info in conf.components.values_mut() {
+        info.channel = Some(channel);
+    }
+    Ok(conf)
+}
+
+/// 从单个服务器获取频道的配置文件并校验同目录下的分离签名version.toml.sig  
+/// 有缓存时带上If-None-Match/If-Modified-Since，服务器返回304时使用缓存
+async fn fetch_signed_conf(
+    cli: &Client,
+    mirror: &Mirror,
+    channel: Channel,
+    key: &ManifestKey,
+    cache: &ManifestCache,
+) -> Result<ValidatedToml> {
+    let url = format!("{}/version.toml", channel.dir(mirror));
+    let cached = cache.load();
+    let mut req = mirror.get(cli, &url);
+    if let Some(meta) = cached.as_ref().map(|c| &c.meta) {
+        if let Some(etag) = &meta.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &meta.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let resp = req.send().await?;
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        let cached = cached.ok_or(anyhow!("{url}: 服务器返回304但本地没有缓存"))?;
+        let conf = parse_signed_conf(&cached.content, &cached.signature, channel, key)?;
+        info!("远程清单未改变，使用缓存");
+        if let Err(e) = cache.touch(&mirror.url) {
+            warn!("更新清单缓存失败: {e}");
+        }
+        return Ok(conf);
+    }
+    if !resp.status().is_success() {
+        return Err(anyhow!("{url}: {}", resp.status()));
+    }
+    let header = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let meta = CacheMeta::new(&mirror.url, header(ETAG), header(LAST_MODIFIED));
+    let content = resp.bytes().await?;
+    let sig_url = format!("{url}.sig");
+    let sig_resp = mirror.get(cli, &sig_url).send().await?;
+    let signature = match sig_resp.status() {
+        status if status.is_success() => sig_resp.bytes().await?.to_vec(),
+        // 显式允许没有签名的清单时才放行
+        StatusCode::NOT_FOUND if !key.required => vec![],
+        status => return Err(anyhow!("获取清单签名失败: {sig_url}: {status}")),
+    };
+    let conf = parse_signed_conf(&content, &signature, channel, key)?;
+    if let Err(e) = cache.save(&content, &signature, &meta) {
+        warn!("保存清单缓存失败: {e}");
+    }
+    Ok(conf)
+}
+
+/// 获取远程配置文件，所有服务器都不可用时使用缓存  
+/// require_signature为false时接受没有发布签名的清单，默认为true
+pub async fn get_remote_conf(mirrors: &[Mirror], channel: Channel, require_signature: bool) -> Result<RemoteConf> {
+    let key = ManifestKey::new(manifest_public_key()?, require_signature);
+    fetch_remote_conf(mirrors, channel, &key, &ManifestCache::new(channel)).await
+}
+
+/// 按顺序尝试每个服务器，连接失败、返回错误状态或签名不符时换下一个  
+/// 都失败时使用签名正确的缓存
+pub async fn fetch_remote_conf(
+    mirrors: &[Mirror],
+    channel: Channel,
+    key: &ManifestKey,
+    cache: &ManifestCache,
+) -> Result<RemoteConf> {
+    let cli = Client::new();
+    let mut errors = vec![];
+    for mirror in mirrors {
+        match fetch_signed_conf(&cli, mirror, channel, key, cache).await {
+            Ok(conf) => {
+                info!("使用服务器 {}", mirror.url);
+                return Ok(RemoteConf { conf, mirror: mirror.url.clone(), stale_since: None });
+            }
+            Err(e) => {
+                warn!("服务器 {} 不可用: {e}", mirror.url);
+                errors.push(e.to_string());
+            }
+        }
+    }
+    let err = anyhow!("所有更新服务器都不可用: {}", errors.join("; "));
+    let Some(cached) = cache.load() else {
+        return Err(err);
+    };
+    match parse_signed_conf(&cached.content, &cached.signature, channel, key) {
+        Ok(conf) => {
+            warn!("{err}，使用{}缓存的清单", cached.meta.fetched);
+            Ok(RemoteConf { conf, mirror: cached.meta.mirror, stale_since: Some(cached.meta.fetched) })
+        }
+        Err(e) => {
+            warn!("清单缓存不可用: {e}");
+            Err(err)
+        }
     }
 }
 
+#[allow(dead_code)]
 pub async fn get_version_data() -> Result<VersionData> {
     let local = get_local_conf()?;
-    let remote = get_remote_conf().await.ok();
-    Ok(VersionData { local, remote })
+    let settings = AppSettings::load()?;
+    let remote = get_remote_conf(&settings.mirrors, settings.channel, !settings.allow_unsigned_manifest).await.ok().map(|r| r.conf);
+    Ok(VersionData {
+        local,
+        invalid: remote.as_ref().map(|r| r.invalid.clone()).unwrap_or_default(),
+        min_updater_version: remote.as_ref().and_then(|r| r.min_updater_version.clone()),
+        remote: remote.map(|r| r.components),
+        channel: settings.channel,
+        stale_since: None,
+    })
 }
 
 #[cfg(test)]
 #[tokio::test]
 async fn test_version_toml() -> Result<()> {
-    let path = env::current_dir()?;
+    let path = std::env::current_dir()?;
     println!("当前工作目录: {:?}", path);
 
     let local_conf = get_local_conf()?;
     println!("Local: {:#?}", local_conf);
 
-    let remote_conf = get_remote_conf().await?;
+    let remote_conf = get_remote_conf(&AppSettings::default().mirrors, Channel::Stable, false).await?;
     println!("Remote: {remote_conf:#?}");
     Ok(())
 }
@@ -194,6 +772,7 @@ async fn test_version_toml() -> Result<()> {
 #[cfg(test)]
 #[test]
 fn test_date() -> Result<()> {
+    use chrono::NaiveDate;
     let date1 = NaiveDate::parse_from_str("2024-05-05", "%Y-%m-%d");
     let date2 = NaiveDate::parse_from_str("2024-03-05", "%Y-%m-%d");
     let res = match (date1, date2) {
@@ -205,3 +784,341 @@ fn test_date() -> Result<()> {
     assert!(res);
     Ok(())
 }
+
+#[cfg(test)]
+#[test]
+fn test_verify() -> Result<()> {
+    let info = VersionInfo {
+        filelist: vec!["test_verify.bin".to_string()],
+        sha1: Some("da39a3ee5e6b4b0d3255bfef95601890afd80709".to_string()),
+        ..Default::default()
+    };
+    // 空文件的sha1
+    fs::write("test_verify.bin.autoupdate", b"")?;
+    assert!(info.verify().is_ok());
+    // 内容不符时报错并删除临时文件
+    fs::write("test_verify.bin.autoupdate", b"broken")?;
+    let err = info.verify().unwrap_err();
+    println!("{err}");
+    assert!(err.to_string().contains("test_verify.bin"));
+    assert!(!fs::exists("test_verify.bin.autoupdate")?);
+    // 旧版sha1只对应filelist[0]，其余文件没有记录Hash时报错
+    let info = VersionInfo {
+        filelist: vec!["test_verify.bin".to_string(), "test_verify2.bin".to_string()],
+        ..info
+    };
+    fs::write("test_verify.bin.autoupdate", b"")?;
+    fs::write("test_verify2.bin.autoupdate", b"")?;
+    let err = info.verify().unwrap_err();
+    assert!(err.to_string().contains("test_verify2.bin"));
+    assert!(!fs::exists("test_verify2.bin.autoupdate")?);
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_signed_manifest() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = ManifestKey::new(signing_key.verifying_key(), true);
+    let content = "[ai_data]\nname = \"AI数据\"\ndate = \"2025-02-01 13:54:57\"\nindex = 2\nfilelist = [\"db/cardDB.json\"]\n";
+    let signature = to_hex(&signing_key.sign(content.as_bytes()).to_bytes());
+
+    let cdn = TestCdn::start().await;
+    let mirror = Mirror::new(&cdn.base_url);
+    let cli = Client::new();
+    let cache = ManifestCache::at("test_signed_cache");
+    // 缺少签名
+    cdn.put("/version.toml", content);
+    assert!(fetch_signed_conf(&cli, &mirror, Channel::Stable, &key, &cache).await.is_err());
+    // 显式允许没有签名时放行，但发布签名后仍然校验
+    let optional = ManifestKey::new(key.key, false);
+    let unsigned_cache = ManifestCache::at("test_unsigned_cache");
+    fetch_signed_conf(&cli, &mirror, Channel::Stable, &optional, &unsigned_cache).await?;
+    cdn.put("/version.toml.sig", to_hex(&[0u8; 64]).as_str());
+    let _ = fs::remove_dir_all("test_unsigned_cache");
+    assert!(fetch_signed_conf(&cli, &mirror, Channel::Stable, &optional, &unsigned_cache).await.is_err());
+    let _ = fs::remove_dir_all("test_unsigned_cache");
+    // 签名正确
+    cdn.put("/version.toml.sig", signature.as_str());
+    let conf = fetch_signed_conf(&cli, &mirror, Channel::Stable, &key, &cache).await?;
+    assert_eq!(conf.components["ai_data"].name, "AI数据");
+    // 清单被篡改
+    cdn.put("/version.toml", content.replace("index = 2", "index = 3"));
+    let err = fetch_signed_conf(&cli, &mirror, Channel::Stable, &key, &cache).await.unwrap_err();
+    println!("{err}");
+    assert!(err.to_string().contains("签名"));
+    let _ = fs::remove_dir_all("test_signed_cache");
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_mirror_failover() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = ManifestKey::new(signing_key.verifying_key(), true);
+    let content = "[auto_update]\nname = \"自动更新工具\"\ndate = \"2025-02-11 13:54:57\"\nindex = 6\nfilelist = [\"uma-autoupdate.exe\"]\n";
+
+    // 连不上的服务器
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let dead = Mirror::new(&format!("http://{}", closed.local_addr()?));
+    drop(closed);
+    // 返回404的服务器
+    let empty = TestCdn::start().await;
+    // 签名不符的服务器
+    let tampered = TestCdn::start().await;
+    tampered.put("/version.toml", content);
+    tampered.put("/version.toml.sig", "00".repeat(64));
+    // 正常的服务器
+    let good = TestCdn::start().await;
+    good.put("/version.toml", content);
+    good.put("/version.toml.sig", to_hex(&signing_key.sign(content.as_bytes()).to_bytes()));
+
+    let mut mirrors = vec![
+        dead,
+        Mirror::new(&empty.base_url),
+        Mirror::new(&tampered.base_url),
+    ];
+    let cache = ManifestCache::at("test_failover_cache");
+    let err = fetch_remote_conf(&mirrors, Channel::Stable, &key, &cache).await.unwrap_err().to_string();
+    println!("{err}");
+    assert!(err.contains("404"));
+    assert!(err.contains("签名"));
+
+    mirrors.push(Mirror::new(&good.base_url));
+    let remote = fetch_remote_conf(&mirrors, Channel::Stable, &key, &cache).await?;
+    assert_eq!(remote.mirror, good.base_url);
+    assert_eq!(remote.conf.components["auto_update"].index, 6);
+    assert!(remote.stale_since.is_none());
+    fs::remove_dir_all("test_failover_cache")?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_offline_cache() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = ManifestKey::new(signing_key.verifying_key(), true);
+    let content = "[ai_data]\nname = \"AI数据\"\ndate = \"2025-02-01 13:54:57\"\nindex = 2\nfilelist = [\"db/cardDB.json\"]\n";
+    let cdn = TestCdn::start().await;
+    cdn.put("/version.toml", content);
+    cdn.put("/version.toml.sig", to_hex(&signing_key.sign(content.as_bytes()).to_bytes()));
+    let mirrors = vec![Mirror::new(&cdn.base_url)];
+    let cache = ManifestCache::at("test_offline_cache");
+    let _ = fs::remove_dir_all("test_offline_cache");
+
+    let remote = fetch_remote_conf(&mirrors, Channel::Stable, &key, &cache).await?;
+    assert!(remote.stale_since.is_none());
+    assert!(cache.load().is_some_and(|c| c.meta.etag.is_some()));
+    // 第二次带上If-None-Match，服务器返回304
+    let remote = fetch_remote_conf(&mirrors, Channel::Stable, &key, &cache).await?;
+    assert_eq!(remote.conf.components["ai_data"].index, 2);
+    assert_eq!(cdn.log(), ["200 /version.toml", "200 /version.toml.sig", "304 /version.toml"]);
+
+    // 服务器不可用时使用缓存
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let dead = vec![Mirror::new(&format!("http://{}", closed.local_addr()?))];
+    drop(closed);
+    let remote = fetch_remote_conf(&dead, Channel::Stable, &key, &cache).await?;
+    assert!(remote.stale_since.is_some());
+    assert_eq!(remote.mirror, cdn.base_url);
+    assert_eq!(remote.conf.components["ai_data"].name, "AI数据");
+
+    // 缓存被篡改时不使用
+    fs::write("test_offline_cache/version.toml", content.replace("index = 2", "index = 3"))?;
+    assert!(fetch_remote_conf(&dead, Channel::Stable, &key, &cache).await.is_err());
+    fs::remove_dir_all("test_offline_cache")?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_install_package() -> Result<()> {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    let make_zip = |entries: &[(&str, &[u8])]| -> Result<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        for (name, data) in entries {
+            writer.start_file(*name, SimpleFileOptions::default())?;
+            writer.write_all(data)?;
+        }
+        Ok(writer.finish()?.into_inner())
+    };
+    let data = make_zip(&[("events.br", b"events"), ("sub/names.br", b"names")])?;
+    let mut info = VersionInfo {
+        filelist: vec!["events.br".to_string(), "sub/names.br".to_string()],
+        package: Some("test_package.zip".to_string()),
+        package_sha1: Some(HashAlgo::Sha1.digest(&data)),
+        install_path: Some("test_package_dir".to_string()),
+        ..Default::default()
+    };
+    fs::write("test_package.zip.autoupdate", &data)?;
+    info.verify_package()?;
+    let mut receipts = HashMap::new();
+    info.install_package(&mut receipts)?;
+    assert_eq!(fs::read("test_package_dir/sub/names.br")?, b"names");
+    assert_eq!(receipts["sub/names.br"].size, 5);
+    assert_eq!(receipts["sub/names.br"].path, info.local_file("sub/names.br")?);
+    assert!(!fs::exists("test_package.zip.autoupdate")?);
+
+    // package_hash优先，Hash不符时删除临时文件
+    info.package_hash = Some(format!("sha256:{}", HashAlgo::Sha256.digest(b"other")));
+    fs::write("test_package.zip.autoupdate", &data)?;
+    assert!(info.verify_package().is_err());
+    assert!(!fs::exists("test_package.zip.autoupdate")?);
+
+    // zip-slip
+    let evil = make_zip(&[("ok.br", b"ok"), ("../evil.br", b"evil")])?;
+    fs::write("test_package.zip.autoupdate", &evil)?;
+    let err = info.install_package(&mut HashMap::new()).unwrap_err();
+    println!("{err}");
+    assert!(!fs::exists("evil.br")?);
+    assert!(!fs::exists("test_package_dir/ok.br")?);
+    fs::remove_file("test_package.zip.autoupdate")?;
+    fs::remove_dir_all("test_package_dir")?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_install_receipts() -> Result<()> {
+    let remote = VersionInfo {
+        name: "测试".to_string(),
+        date: "2025-04-01 00:00:00".to_string(),
+        filelist: vec!["test_receipt_a.bin".to_string(), "test_receipt_b.bin".to_string()],
+        hashes: HashMap::from([(
+            "test_receipt_a.bin".to_string(),
+            format!("sha256:{}", HashAlgo::Sha256.digest(b"new a")),
+        )]),
+        install_path: Some("test_receipt_dir".to_string()),
+        ..Default::default()
+    };
+    // b没有下载，只安装了a
+    fs::write("test_receipt_a.bin.autoupdate", b"new a")?;
+    let mut receipts = HashMap::new();
+    remote.install(&mut receipts)?;
+    assert_eq!(receipts.len(), 1);
+    let receipt = &receipts["test_receipt_a.bin"];
+    assert_eq!(receipt.size, 5);
+    assert!(receipt.hash.starts_with("sha256:"));
+
+    let mut local = VersionInfo { receipts, ..remote.clone() };
+    assert_eq!(remote.check_receipts(&local), Some(false));
+    fs::write("test_receipt_b.bin.autoupdate", b"new b")?;
+    remote.install(&mut local.receipts)?;
+    assert_eq!(remote.check_receipts(&local), Some(true));
+    // 安装后文件被改动
+    fs::write("test_receipt_dir/test_receipt_b.bin", b"changed")?;
+    assert_eq!(remote.check_receipts(&local), Some(false));
+    // 旧记录没有回执
+    assert_eq!(remote.check_receipts(&remote), None);
+
+    // 绕过清单校验的路径在安装时也会被拒绝
+    let evil = VersionInfo {
+        filelist: vec!["../test_receipt_evil.bin".to_string()],
+        ..remote.clone()
+    };
+    assert!(evil.local_file("../test_receipt_evil.bin").is_err());
+    assert!(evil.install(&mut HashMap::new()).is_err());
+    fs::remove_dir_all("test_receipt_dir")?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_installed_record() {
+    let info = |ver: &str| VersionInfo {
+        name: "AI数据".to_string(),
+        date: "2025-04-01 00:00:00".to_string(),
+        filelist: vec!["a.bin".to_string(), "b.bin".to_string()],
+        ver: Some(ver.to_string()),
+        ..Default::default()
+    };
+    let receipt = |hash: &str| FileReceipt {
+        path: "a.bin".to_string(),
+        size: 1,
+        hash: hash.to_string(),
+        installed: "2025-04-01 00:00:00".to_string(),
+    };
+    let old = VersionInfo {
+        receipts: HashMap::from([("a.bin".to_string(), receipt("old"))]),
+        ..info("0.1.0")
+    };
+    let data = VersionData {
+        local: Some(HashMap::from([("ai_data".to_string(), old)])),
+        remote: Some(HashMap::from([("ai_data".to_string(), info("0.2.0"))])),
+        ..Default::default()
+    };
+    let partial = HashMap::from([("a.bin".to_string(), receipt("new"))]);
+    // 安装成功时记录远程版本
+    let ok = data.installed_record("ai_data", None, partial.clone(), true).expect("no record");
+    assert_eq!(ok.ver.as_deref(), Some("0.2.0"));
+    // 安装失败时保留原来的版本，只更新已写入文件的回执
+    let failed = data.installed_record("ai_data", None, partial.clone(), false).expect("no record");
+    assert_eq!(failed.ver.as_deref(), Some("0.1.0"));
+    assert_eq!(failed.receipts["a.bin"].hash, "new");
+    // 原来没有安装时不写入失败的版本
+    let fresh = VersionData { local: None, ..data };
+    assert!(fresh.installed_record("ai_data", None, partial, false).is_none());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fetch_notes() -> Result<()> {
+    use crate::test_cdn::TestCdn;
+
+    let empty = TestCdn::start().await;
+    let cdn = TestCdn::start().await;
+    let content = "# 更新内容\n- 新增数据";
+    cdn.put("/beta/ai_data/notes.md", content);
+    let hash: FileHash = format!("sha256:{}", HashAlgo::Sha256.digest(content.as_bytes())).parse()?;
+    let mirrors = vec![Mirror::new(&empty.base_url), Mirror::new(&cdn.base_url)];
+    let notes = fetch_notes(&mirrors, Channel::Beta, "ai_data", "notes.md", &hash).await?;
+    assert!(notes.starts_with("# 更新内容"));
+    let url = format!("{}/beta/ai_data/notes.md", cdn.base_url);
+    assert_eq!(fetch_notes(&[], Channel::Stable, "ai_data", &url, &hash).await?, notes);
+    assert!(fetch_notes(&mirrors, Channel::Stable, "ai_data", "notes.md", &hash).await.is_err());
+    // 被篡改的更新说明
+    cdn.put("/beta/ai_data/notes.md", "[点击](https://evil.example/&calc)");
+    let err = fetch_notes(&mirrors, Channel::Beta, "ai_data", "notes.md", &hash).await.unwrap_err();
+    assert!(err.to_string().contains("sha256"), "{err}");
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_remove_orphans() -> Result<()> {
+    let info = |filelist: &[&str]| VersionInfo {
+        name: "测试".to_string(),
+        date: "2025-04-01 00:00:00".to_string(),
+        filelist: filelist.iter().map(|f| f.to_string()).collect(),
+        install_path: Some("test_orphan_dir".to_string()),
+        ..Default::default()
+    };
+    fs::create_dir_all("test_orphan_dir/sub")?;
+    for f in ["a.bin", "sub/b.bin", "c.bin"] {
+        fs::write(format!("test_orphan_dir/{f}"), f)?;
+    }
+    let old = info(&["a.bin", "sub/b.bin", "missing.bin"]);
+    let new = info(&["a.bin", "c.bin"]);
+    let removed = new.remove_orphans(&old)?;
+    assert_eq!(removed, [old.local_file("sub/b.bin")?]);
+    assert!(fs::exists("test_orphan_dir/a.bin")?);
+    assert!(!fs::exists("test_orphan_dir/sub/b.bin")?);
+
+    assert_eq!(new.uninstall()?.len(), 2);
+    assert!(!fs::exists("test_orphan_dir/c.bin")?);
+    let updater = VersionInfo { kind: Some(ComponentKind::SelfUpdate), ..new };
+    assert!(updater.uninstall().is_err());
+    fs::remove_dir_all("test_orphan_dir")?;
+    Ok(())
+}
@@ -1,12 +1,19 @@
 //! version_info  
 //! 显示单个app版本信息的组件
+use crate::download::DownloadProgress;
+use crate::installer::ComponentKind;
+use crate::settings::Channel;
+use crate::utils::{format_eta, format_size};
+use crate::version::*;
 use crate::version_toml::*;
 use crate::Message;
 use anyhow::{anyhow, Result};
-use iced::widget::{button, container, row, text};
+use iced::widget::{button, column, container, progress_bar, row, text};
 use iced::{Center, Color, Element, Fill, FillPortion, Shadow, Vector};
 use serde::{Deserialize, Serialize};
 use log::info;
+use std::collections::HashMap;
+use std::path::Path;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct VersionWidget {
@@ -15,14 +22,20 @@ pub struct VersionWidget {
     pub index: u32,
     pub local: Option<VersionInfo>,
     pub remote: Option<VersionInfo>,
-    pub local_sha1: Option<String>
-}
-
-fn get_update_time(opt: &Option<VersionInfo>) -> String {
-    opt.as_ref()
-        .and_then(|v| v.date.split(".").nth(0))
-        .map(|st| st.replace(" ", "\n"))
-        .unwrap_or("无".to_string())
+    pub local_hash: Option<String>,
+    /// 远程清单中该组件的格式错误
+    pub error: Option<String>,
+    /// 前置组件无法满足的原因
+    pub blocked: Option<String>,
+    /// 远程版本所在的频道
+    pub channel: Channel,
+    /// 已弃用或已从远程清单中移除，可以卸载
+    pub deprecated: bool,
+    /// 重试多次后仍下载失败的原因
+    pub failed: Option<String>,
+    /// 正在下载时所有文件合并后的进度
+    #[serde(skip)]
+    pub progress: Option<DownloadProgress>
 }
 
 macro_rules! def_align {
@@ -51,6 +64,7 @@ fn bg_style(color: Color) -> container::Style {
     }
 }
 
+#[allow(dead_code)]
 fn btn_style(theme: &iced::Theme, status: button::Status) -> button::Style {
     let mut sty = button::primary(theme, status);
     sty.shadow = Shadow {
@@ -73,28 +87,156 @@ impl VersionWidget {
             .as_ref()
             .and_then(|remote| remote.get(key))
             .cloned();
-        let local_sha1 = remote.as_ref().and_then(|r| r.get_local_sha1().unwrap_or(None));
+        let local_hash = remote
+            .as_ref()
+            .and_then(|r| r.get_local_hash().unwrap_or(None))
+            .map(|h| h.to_string());
+        let invalid = data.invalid.get(key);
+        // 远程清单里没有这个组件时视为已移除，从其他频道安装的除外
+        let deprecated = match &remote {
+            Some(r) => r.deprecated,
+            None => {
+                data.remote.is_some()
+                    && invalid.is_none()
+                    && local.as_ref().is_some_and(|l| l.channel.unwrap_or_default() == data.channel)
+            }
+        };
         let pick = remote.as_ref().or(local.as_ref());
-        let index = pick.map(|v| v.index).unwrap_or(0);
-        let name = pick.map(|v| v.name.clone()).unwrap_or(key.to_string());
+        let index = pick.map(|v| v.index)
+            .or(invalid.map(|c| c.index))
+            .unwrap_or(0);
+        let name = pick.map(|v| v.name.clone())
+            .or(invalid.map(|c| c.name.clone()))
+            .unwrap_or(key.to_string());
         Self {
             key: key.to_string(),
             name,
             index,
             local,
             remote,
-            local_sha1
+            local_hash,
+            error: invalid.map(|c| c.error.clone()),
+            blocked: None,
+            channel: data.channel,
+            deprecated,
+            failed: None,
+            progress: None
+        }
+    }
+
+    /// 远程版本相对本地记录的状态
+    pub fn update_state(&self) -> UpdateState {
+        match (&self.local, &self.remote) {
+            (Some(local), Some(remote)) => remote.compare(local),
+            _ => UpdateState::Unknown,
         }
     }
 
     pub fn needs_update(&self) -> (bool, &'static str) {
-        match (self.local_sha1.as_deref(), self.remote.as_ref().and_then(|r| r.sha1.as_deref())) {
-            (Some(l), Some(r)) if l == r => (false, "已经是最新版本"),
-            (_, None) => (false, "未连接到更新服务器"),
-            _ => (true, "需要更新"),
+        if self.error.is_some() {
+            return (false, "远程清单格式错误");
+        }
+        if self.deprecated {
+            return match &self.remote {
+                Some(_) => (false, "已弃用"),
+                None => (false, "服务器已移除该组件"),
+            };
+        }
+        match self.compare_local() {
+            (true, _) if self.blocked.is_some() => (false, "前置组件不满足"),
+            (true, _) if self.failed.is_some() => (true, "下载失败"),
+            ret => ret,
         }
     }
-    pub fn view(&self) -> Element<Message> {
+
+    fn compare_local(&self) -> (bool, &'static str) {
+        let Some(remote) = self.remote.as_ref() else {
+            if self.other_channel() {
+                return (false, "当前频道没有该组件");
+            }
+            return (false, "未连接到更新服务器");
+        };
+        if self.local_hash.is_none() {
+            return (true, "未安装");
+        }
+        let remote_hash = remote
+            .filelist
+            .first()
+            .and_then(|f| remote.expected_hash(f).unwrap_or(None))
+            .map(|h| h.to_string());
+        let same_file = remote_hash.is_some() && remote_hash == self.local_hash;
+        let receipts = self.local.as_ref().and_then(|l| remote.check_receipts(l));
+        match self.update_state() {
+            UpdateState::Newer => (true, "有新版本"),
+            // 从其他频道切换回来时允许降级
+            UpdateState::Older if self.other_channel() => (true, "可降级到当前频道"),
+            UpdateState::Older => (false, "本地版本较新"),
+            // 版本相同时以安装回执为准，没有回执的旧记录以文件Hash为准
+            UpdateState::Same if receipts == Some(false) => (true, "安装不完整"),
+            UpdateState::Same if receipts == Some(true) => (false, "已经是最新版本"),
+            _ if same_file => (false, "已经是最新版本"),
+            UpdateState::Same if remote_hash.is_none() => (false, "已经是最新版本"),
+            UpdateState::Same => (true, "本地文件不一致"),
+            UpdateState::Unknown => (true, "需要更新"),
+        }
+    }
+
+    /// 本地记录有备份且备份还在时可以回滚
+    pub fn can_rollback(&self) -> bool {
+        self.local
+            .as_ref()
+            .and_then(|l| l.backup.as_deref())
+            .is_some_and(|dir| Path::new(dir).exists())
+    }
+
+    /// 弃用的组件已安装时可以卸载，更新工具自身除外
+    pub fn can_uninstall(&self) -> bool {
+        self.deprecated
+            && self.kind() != ComponentKind::SelfUpdate
+            && self.local.is_some()
+    }
+
+    /// 远程版本是否有更新说明
+    pub fn has_notes(&self) -> bool {
+        self.remote
+            .as_ref()
+            .is_some_and(|r| r.notes.is_some() || r.notes_url.is_some())
+    }
+
+    /// 本地版本是否来自其他频道
+    fn other_channel(&self) -> bool {
+        self.local
+            .as_ref()
+            .is_some_and(|l| l.channel.unwrap_or_default() != self.channel)
+    }
+
+    /// 形如`1.5 MB / 4.0 MB · 512.0 KB/s · 剩余6秒`
+    pub fn progress_text(&self) -> Option<String> {
+        let p = self.progress.as_ref()?;
+        let mut parts = vec![match p.total {
+            Some(total) => format!("{} / {}", format_size(p.downloaded), format_size(total)),
+            None => format_size(p.downloaded),
+        }];
+        if p.rate > 0.0 {
+            parts.push(format!("{}/s", format_size(p.rate as u64)));
+        }
+        if let Some(eta) = p.eta {
+            parts.push(format!("剩余{}", format_eta(eta)));
+        }
+        Some(parts.join(" · "))
+    }
+
+    /// 形如`0.1.2 → 0.1.3`，没有ver时显示日期
+    pub fn version_text(&self) -> String {
+        let label = |v: &Option<VersionInfo>| {
+            v.as_ref()
+                .map(|v| v.version().to_string())
+                .unwrap_or("无".to_string())
+        };
+        format!("{} → {}", label(&self.local), label(&self.remote))
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
         let name = text(&self.name)
             .size(20)
             .width(FillPortion(3))
@@ -107,45 +249,211 @@ impl VersionWidget {
        // )
        // .style(|_| bg_style(Color::from_rgba8(128, 255, 128, 0.85)));
         let (needs_update, reason) = self.needs_update();
-        let local_row = def_align!(
-            container(text!("{reason}")), 3
-        ).style(|_|
-            bg_style(Color::from_rgba8(128, 255, 128, 0.85))
-        );
+        let detail = self.error.as_ref()
+            .or(self.blocked.as_ref().filter(|_| !needs_update))
+            .or(self.failed.as_ref().filter(|_| needs_update));
+        let local_row = match (self.progress_text(), detail) {
+            // 正在下载时显示进度条
+            (Some(status), _) => def_align!(
+                container(column![
+                    progress_bar(0.0..=1.0, self.progress.as_ref().map_or(0.0, |p| p.fraction())).height(10),
+                    text(status).size(12)
+                ].spacing(4).align_x(Center)).padding([0, 8]), 3
+            ).style(|_|
+                bg_style(Color::from_rgba8(128, 255, 128, 0.85))
+            ),
+            // 格式错误、被前置组件阻塞或下载失败时显示具体原因
+            (None, Some(error)) => def_align!(
+                container(text!("{reason}: {error}").size(12)), 3
+            ).style(|_|
+                bg_style(Color::from_rgba8(255, 96, 96, 0.85))
+            ),
+            (None, None) => def_align!(
+                container(text!("{reason}")), 3
+            ).style(|_|
+                bg_style(Color::from_rgba8(128, 255, 128, 0.85))
+            ),
+        };
         let remote_row = def_align!(
-            container(text!("最新版本: {}", get_update_time(&self.remote))),
+            container(text!("{}", self.version_text())),
             3
         ).style(|_|
             bg_style(Color::from_rgba8(192, 0, 255, 0.85))
         );
 
-        let on_press_msg = if needs_update {
+        let on_press_msg = if needs_update && self.progress.is_none() {
             Some(Message::OnClickUpdate(self.clone()))
         } else {
             None
         };
 
-        let btn_update = button(text("更新").color(Color::WHITE).align_y(Center))
-            .style(button::primary)
-            .padding([32, 40])
-            .on_press_maybe(on_press_msg)
+        let label = if self.failed.is_some() && needs_update {
+            "重试"
+        } else if self.update_state() == UpdateState::Older {
+            "降级"
+        } else {
+            "更新"
+        };
+        let btn_update = if self.can_uninstall() {
+            button(text("卸载").color(Color::WHITE).align_y(Center))
+                .style(button::danger)
+                .on_press(Message::OnClickUninstall(self.clone()))
+        } else {
+            button(text(label).color(Color::WHITE).align_y(Center))
+                .style(button::primary)
+                .on_press_maybe(on_press_msg)
+        }
+        .padding([32, 40])
+        .height(Fill);
+
+        let btn_rollback = button(text("回滚").color(Color::WHITE).align_y(Center))
+            .style(button::secondary)
+            .padding([32, 16])
+            .on_press_maybe(self.can_rollback().then(|| Message::OnClickRollback(self.clone())))
+            .height(Fill);
+
+        let btn_notes = button(text("说明").color(Color::WHITE).align_y(Center))
+            .style(button::secondary)
+            .padding([32, 16])
+            .on_press_maybe(self.has_notes().then(|| Message::OnClickNotes(self.clone())))
             .height(Fill);
 
-        container(row![name, local_row, remote_row, btn_update].spacing(20))
+        container(row![name, local_row, remote_row, btn_notes, btn_update, btn_rollback].spacing(20))
             .padding(5)
             .into()
     }
 
-    pub fn verify(&self) -> bool {
-        self.remote.as_ref().map(|x| x.verify()).unwrap_or(false)
+    pub fn verify(&self) -> Result<()> {
+        self.remote
+            .as_ref()
+            .map(|x| x.verify())
+            .unwrap_or(Err(anyhow!("未获取远程版本")))
     }
-    pub fn replace(&mut self) -> Result<()> {
+    pub fn verify_package(&self) -> Result<()> {
         self.remote
             .as_ref()
-            .map(|x| x.install())
-            .unwrap_or(Err(anyhow!("未获取远程版本")))?;
-        self.local_sha1 = self.remote.as_ref().and_then(|r| r.get_local_sha1().unwrap_or(None));
-        info!("After replace: Local {:?}", self.local_sha1);
+            .map(|x| x.verify_package())
+            .unwrap_or(Err(anyhow!("未获取远程版本")))
+    }
+    fn refresh_local_hash(&mut self) {
+        self.local_hash = self
+            .remote
+            .as_ref()
+            .and_then(|r| r.get_local_hash().unwrap_or(None))
+            .map(|h| h.to_string());
+        info!("After replace: Local {:?}", self.local_hash);
+    }
+    /// 按组件类型安装远程版本
+    pub fn replace(&mut self, package: bool, receipts: &mut HashMap<String, FileReceipt>) -> Result<()> {
+        let remote = self.remote.as_ref().ok_or(anyhow!("未获取远程版本"))?;
+        remote.kind().installer().install(remote, package, receipts)?;
+        self.refresh_local_hash();
         Ok(())
     }
+
+    /// 保存本地记录以后完成安装，更新工具自身在这里替换exe
+    pub fn finish(&self) -> Result<()> {
+        let remote = self.remote.as_ref().ok_or(anyhow!("未获取远程版本"))?;
+        remote.kind().installer().finish(remote)
+    }
+
+    /// 完成安装后是否需要重启更新工具
+    pub fn needs_restart(&self) -> bool {
+        self.kind().installer().restart()
+    }
+
+    /// 组件类型，以远程清单为准
+    pub fn kind(&self) -> ComponentKind {
+        self.remote
+            .as_ref()
+            .or(self.local.as_ref())
+            .map(|v| v.kind())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_downgrade_from_other_channel() {
+    let info = |ver: &str, channel: Option<Channel>| VersionInfo {
+        name: "AI数据".to_string(),
+        date: "2025-04-01 00:00:00".to_string(),
+        filelist: vec!["db/cardDB.json".to_string()],
+        ver: Some(ver.to_string()),
+        channel,
+        ..Default::default()
+    };
+    let mut w = VersionWidget {
+        key: "ai_data".to_string(),
+        name: "AI数据".to_string(),
+        index: 2,
+        local: Some(info("0.2.0-beta.1", Some(Channel::Beta))),
+        remote: Some(info("0.1.3", None)),
+        local_hash: Some("sha1:da39a3ee5e6b4b0d3255bfef95601890afd80709".to_string()),
+        error: None,
+        blocked: None,
+        channel: Channel::Stable,
+        deprecated: false,
+        failed: None,
+        progress: None,
+    };
+    assert_eq!(w.update_state(), UpdateState::Older);
+    assert!(w.needs_update().0);
+    assert_eq!(w.version_text(), "0.2.0-beta.1 → 0.1.3");
+    // 下载中显示进度
+    w.progress = Some(DownloadProgress {
+        downloaded: 1536,
+        total: Some(4096),
+        rate: 512.0,
+        eta: Some(std::time::Duration::from_secs(5)),
+        ..Default::default()
+    });
+    assert_eq!(w.progress_text().as_deref(), Some("1.5 KB / 4.0 KB · 512 B/s · 剩余5秒"));
+    w.progress = None;
+    // 下载失败后可以重试
+    w.failed = Some("db/cardDB.json: 连接超时".to_string());
+    assert_eq!(w.needs_update(), (true, "下载失败"));
+    w.failed = None;
+    // 同一频道里不降级
+    w.channel = Channel::Beta;
+    assert!(!w.needs_update().0);
+    // 弃用后只能卸载
+    w.remote.as_mut().unwrap().deprecated = true;
+    w.deprecated = true;
+    assert!(!w.needs_update().0);
+    assert!(w.can_uninstall());
+}
+
+#[cfg(test)]
+#[test]
+fn test_removed_component() {
+    let info = |name: &str, channel: Channel| VersionInfo {
+        name: name.to_string(),
+        date: "2025-04-01 00:00:00".to_string(),
+        filelist: vec![format!("test_removed/{name}.json")],
+        channel: Some(channel),
+        ..Default::default()
+    };
+    let local = VersionToml::from([
+        ("ai_data".to_string(), info("ai_data", Channel::Stable)),
+        ("old_data".to_string(), info("old_data", Channel::Stable)),
+        ("beta_data".to_string(), info("beta_data", Channel::Beta)),
+    ]);
+    let remote = VersionToml::from([("ai_data".to_string(), info("ai_data", Channel::Stable))]);
+    let data = VersionData {
+        local: Some(local),
+        remote: Some(remote),
+        channel: Channel::Stable,
+        ..Default::default()
+    };
+    // 远程清单里删掉的组件也有一行，可以卸载
+    assert_eq!(data.component_keys(), ["ai_data", "beta_data", "old_data"]);
+    let removed = VersionWidget::new(&data, "old_data");
+    assert_eq!(removed.needs_update(), (false, "服务器已移除该组件"));
+    assert!(removed.can_uninstall());
+    // 从其他频道安装的组件不算被移除
+    let other = VersionWidget::new(&data, "beta_data");
+    assert!(!other.deprecated);
+    assert_eq!(other.needs_update(), (false, "当前频道没有该组件"));
+    assert!(!VersionWidget::new(&data, "ai_data").deprecated);
 }